@@ -0,0 +1,203 @@
+use crate::ast::{CaseCondition, Expr, Interval, Openness, Piecewise};
+use crate::subst::within;
+use anyhow::Result;
+
+type BinOp = fn(Box<Expr<f64>>, Box<Expr<f64>>) -> Expr<f64>;
+
+/// Whether `a` runs out before `b` does.
+fn ends_before(a: &Interval<f64>, b: &Interval<f64>) -> bool {
+    a.high_val < b.high_val
+        || (a.high_val == b.high_val
+            && a.high_openness == Openness::Open
+            && b.high_openness == Openness::Closed)
+}
+
+/// Join neighbouring stretches that share an expression.
+fn merge_adjacent(segments: Vec<(Interval<f64>, Expr<f64>)>) -> Vec<(Interval<f64>, Expr<f64>)> {
+    let mut merged: Vec<(Interval<f64>, Expr<f64>)> = Vec::new();
+    for (interval, expr) in segments {
+        match merged.last_mut() {
+            Some((last, last_expr))
+                if *last_expr == expr
+                    && last.high_val == interval.low_val
+                    && last.high_openness != interval.low_openness =>
+            {
+                last.high_val = interval.high_val;
+                last.high_openness = interval.high_openness;
+            }
+            _ => merged.push((interval, expr)),
+        }
+    }
+    merged
+}
+
+/// Rebuild a piecewise function from stretches that cover the whole line.
+/// The leftmost stretch's expression becomes `otherwise`, and every other
+/// stretch with that same expression is left to fall through to it.
+fn from_partition(lhs: &str, segments: Vec<(Interval<f64>, Expr<f64>)>) -> Piecewise<f64> {
+    let mut segments = merge_adjacent(segments).into_iter();
+    let (_, otherwise) = segments
+        .next()
+        .expect("a partition of the real line is never empty");
+    let mut cases: Vec<_> = segments
+        .filter(|(_, expr)| *expr != otherwise)
+        .map(|(interval, expr)| (CaseCondition::Interval(interval), Box::new(expr)))
+        .collect();
+    cases.push((CaseCondition::Otherwise, Box::new(otherwise)));
+    Piecewise::new(lhs.to_string(), cases)
+}
+
+/// Rewrite `cond` to test `replacement` wherever it tested `var`, including
+/// the implicit argument of bare intervals.
+fn substitute_condition(
+    cond: &CaseCondition<f64>,
+    var: &str,
+    replacement: &Expr<f64>,
+) -> CaseCondition<f64> {
+    use CaseCondition::*;
+    let sub = |c: &CaseCondition<f64>| Box::new(substitute_condition(c, var, replacement));
+    match cond {
+        Otherwise => Otherwise,
+        Interval(i) => within(replacement, i),
+        In(v, i) if v == var => within(replacement, i),
+        In(v, i) => In(v.clone(), i.clone()),
+        Compare(op, a, b) => Compare(
+            *op,
+            Box::new(a.substitute(var, replacement)),
+            Box::new(b.substitute(var, replacement)),
+        ),
+        And(a, b) => And(sub(a), sub(b)),
+        Or(a, b) => Or(sub(a), sub(b)),
+        Not(c) => Not(sub(c)),
+        NonZero(e) => NonZero(Box::new(e.substitute(var, replacement))),
+    }
+}
+
+impl<T> Piecewise<T> {
+    /// The piecewise function with `expr` as its only (`otherwise`) case.
+    pub fn from_expr(lhs: String, expr: Expr<T>) -> Self {
+        Piecewise::new(lhs, vec![(CaseCondition::Otherwise, Box::new(expr))])
+    }
+}
+
+impl Piecewise<f64> {
+    /// Combine the two functions case by case over the common refinement of
+    /// their partitions, simplifying each resulting expression.
+    fn zip_with(&self, other: &Piecewise<f64>, op: BinOp) -> Result<Piecewise<f64>> {
+        let (a, b) = (self.partition()?, other.partition()?);
+        let (mut i, mut j) = (0, 0);
+        let mut segments = Vec::new();
+        while i < a.len() && j < b.len() {
+            if let Some(both) = a[i].0.intersection(&b[j].0) {
+                let expr = op(Box::new(a[i].1.clone()), Box::new(b[j].1.clone()));
+                segments.push((both, expr.simplify()));
+            }
+            let (a_first, b_first) = (ends_before(&a[i].0, &b[j].0), ends_before(&b[j].0, &a[i].0));
+            if !b_first {
+                i += 1;
+            }
+            if !a_first {
+                j += 1;
+            }
+        }
+        Ok(from_partition(&self.lhs, segments))
+    }
+
+    pub fn add(&self, other: &Piecewise<f64>) -> Result<Piecewise<f64>> {
+        self.zip_with(other, Expr::Add)
+    }
+
+    pub fn sub(&self, other: &Piecewise<f64>) -> Result<Piecewise<f64>> {
+        self.zip_with(other, Expr::Sub)
+    }
+
+    pub fn mul(&self, other: &Piecewise<f64>) -> Result<Piecewise<f64>> {
+        self.zip_with(other, Expr::Mul)
+    }
+
+    pub fn div(&self, other: &Piecewise<f64>) -> Result<Piecewise<f64>> {
+        self.zip_with(other, Expr::Div)
+    }
+
+    pub fn add_expr(&self, expr: &Expr<f64>) -> Result<Piecewise<f64>> {
+        self.add(&Piecewise::from_expr(self.lhs.clone(), expr.clone()))
+    }
+
+    pub fn sub_expr(&self, expr: &Expr<f64>) -> Result<Piecewise<f64>> {
+        self.sub(&Piecewise::from_expr(self.lhs.clone(), expr.clone()))
+    }
+
+    pub fn mul_expr(&self, expr: &Expr<f64>) -> Result<Piecewise<f64>> {
+        self.mul(&Piecewise::from_expr(self.lhs.clone(), expr.clone()))
+    }
+
+    pub fn div_expr(&self, expr: &Expr<f64>) -> Result<Piecewise<f64>> {
+        self.div(&Piecewise::from_expr(self.lhs.clone(), expr.clone()))
+    }
+
+    /// The same function with neighbouring cases that share an expression
+    /// joined into one, and cases that match `otherwise` dropped.
+    pub fn merge_cases(&self) -> Result<Piecewise<f64>> {
+        let segments = self
+            .partition()?
+            .into_iter()
+            .map(|(interval, expr)| (interval, expr.clone()))
+            .collect();
+        Ok(from_partition(&self.lhs, segments))
+    }
+
+    /// The function on `interval` only; everywhere else it is NaN.
+    pub fn restrict(&self, interval: &Interval<f64>) -> Result<Piecewise<f64>> {
+        let segments = self
+            .partition()?
+            .into_iter()
+            .filter_map(|(s, expr)| Some((s.intersection(interval)?, expr.clone())))
+            .collect();
+        let mut cases: Vec<_> = merge_adjacent(segments)
+            .into_iter()
+            .map(|(interval, expr)| (CaseCondition::Interval(interval), Box::new(expr)))
+            .collect();
+        cases.push((CaseCondition::Otherwise, Box::new(Expr::Const(f64::NAN))));
+        Ok(Piecewise::new(self.lhs.clone(), cases))
+    }
+
+    /// Feed `inner` into this function: every use of `inner.lhs` as an input,
+    /// including the argument bare intervals test, is replaced by `inner`'s
+    /// value.
+    ///
+    /// The result has a block of cases for every stretch of `inner`'s
+    /// partition, each guarded by that stretch and by this function's
+    /// conditions rewritten as comparisons on the inner expression. `inner`
+    /// must use only interval cases; this function may use any.
+    pub fn compose(&self, inner: &Piecewise<f64>) -> Result<Piecewise<f64>> {
+        self.well_formed()?;
+        let segments = inner.partition()?;
+        let var = &inner.lhs;
+        let (body, otherwise) = self.cases.split_at(self.cases.len() - 1);
+        let otherwise = &otherwise[0].1;
+
+        let mut cases = Vec::new();
+        for (k, (stretch, g)) in segments.iter().enumerate() {
+            let guard = |cond: CaseCondition<f64>| match segments.len() {
+                1 => cond,
+                _ => CaseCondition::And(
+                    Box::new(CaseCondition::Interval(stretch.clone())),
+                    Box::new(cond),
+                ),
+            };
+            for (cond, f) in body {
+                cases.push((
+                    guard(substitute_condition(cond, var, g)),
+                    Box::new(f.substitute(var, g)),
+                ));
+            }
+            let fallback = if k + 1 == segments.len() {
+                CaseCondition::Otherwise
+            } else {
+                CaseCondition::Interval(stretch.clone())
+            };
+            cases.push((fallback, Box::new(otherwise.substitute(var, g))));
+        }
+        Ok(Piecewise::new(self.lhs.clone(), cases))
+    }
+}
@@ -0,0 +1,230 @@
+use crate::ast::{CaseCondition, Equation, Expr, Interval, Piecewise};
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+
+impl<T> Expr<T> {
+    /// The distinct variables this expression reads, in sorted order. Names
+    /// bound by `let` are not read from outside within their body.
+    pub fn free_vars(&self) -> BTreeSet<String> {
+        match self {
+            Expr::Var(s) => BTreeSet::from([s.clone()]),
+            Expr::Let(name, value, body) => {
+                let mut vars = body.free_vars();
+                vars.remove(name);
+                &value.free_vars() | &vars
+            }
+            e => {
+                let own = match e {
+                    Expr::Bool(c) | Expr::If(c, _, _) => c.tested_vars(),
+                    _ => vec![],
+                };
+                let mut vars: BTreeSet<String> = own.into_iter().map(str::to_string).collect();
+                for child in e.children() {
+                    vars.extend(child.free_vars());
+                }
+                vars
+            }
+        }
+    }
+
+    pub fn contains_var(&self, var: &str) -> bool {
+        self.free_vars().contains(var)
+    }
+
+    /// Checks the `let` bindings: none may reuse the name of an input or of
+    /// an enclosing binding, and none may use its own name in its value,
+    /// where that name is not bound yet.
+    pub fn check_scopes(&self) -> Result<()> {
+        self.check_scopes_in(&mut Vec::new(), &self.free_vars())
+    }
+
+    fn check_scopes_in<'a>(
+        &'a self,
+        scope: &mut Vec<&'a str>,
+        inputs: &BTreeSet<String>,
+    ) -> Result<()> {
+        let Expr::Let(name, value, body) = self else {
+            return self
+                .children()
+                .into_iter()
+                .try_for_each(|child| child.check_scopes_in(scope, inputs));
+        };
+        if scope.contains(&name.as_str()) {
+            return Err(anyhow!("\"{}\" is already bound by an enclosing let", name));
+        }
+        if value.contains_var(name) {
+            return Err(anyhow!("\"{}\" is unbound in its own definition", name));
+        }
+        if inputs.contains(name) {
+            return Err(anyhow!(
+                "let binding \"{}\" shadows the input of the same name",
+                name
+            ));
+        }
+        value.check_scopes_in(scope, inputs)?;
+        scope.push(name);
+        body.check_scopes_in(scope, inputs)?;
+        scope.pop();
+        Ok(())
+    }
+
+    /// Every name bound by a `let` in the expression.
+    pub fn bound_vars(&self) -> BTreeSet<String> {
+        self.subexprs()
+            .filter_map(|e| match e {
+                Expr::Let(name, _, _) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether some condition inside the expression has a bare interval.
+    pub(crate) fn has_bare_interval(&self) -> bool {
+        self.subexprs().any(|e| match e {
+            Expr::Bool(c) | Expr::If(c, _, _) => c.tests_argument(),
+            _ => false,
+        })
+    }
+}
+
+impl<T> Equation<T> {
+    /// The parameters needed to evaluate the right-hand side, in sorted order.
+    pub fn inputs(&self) -> BTreeSet<String> {
+        self.rhs().free_vars()
+    }
+
+    /// An equation is well formed when its left-hand side is not also one of
+    /// its own inputs, i.e. it is a definition rather than a recurrence.
+    pub fn well_formed(&self) -> Result<()> {
+        if self.rhs().contains_var(self.lhs()) {
+            return Err(anyhow!(
+                "Malformed equation: \"{}\" appears on its own right-hand side",
+                self.lhs()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<T> CaseCondition<T> {
+    /// The variables the condition tests, in sorted order. Bare intervals
+    /// test the implicit argument and contribute none.
+    pub fn free_vars(&self) -> BTreeSet<String> {
+        match self {
+            CaseCondition::Otherwise | CaseCondition::Interval(_) => BTreeSet::new(),
+            CaseCondition::In(v, _) => BTreeSet::from([v.clone()]),
+            CaseCondition::Compare(_, a, b) => &a.free_vars() | &b.free_vars(),
+            CaseCondition::And(a, b) | CaseCondition::Or(a, b) => &a.free_vars() | &b.free_vars(),
+            CaseCondition::Not(c) => c.free_vars(),
+            CaseCondition::NonZero(e) => e.free_vars(),
+        }
+    }
+
+    /// Whether a bare interval, which tests the implicit argument, appears
+    /// outside the expressions the condition compares.
+    pub(crate) fn tests_argument(&self) -> bool {
+        match self {
+            CaseCondition::Interval(_) => true,
+            CaseCondition::And(a, b) | CaseCondition::Or(a, b) => {
+                a.tests_argument() || b.tests_argument()
+            }
+            CaseCondition::Not(c) => c.tests_argument(),
+            _ => false,
+        }
+    }
+}
+
+impl<T> Piecewise<T> {
+    /// Every variable read by a condition or a case, in sorted order.
+    pub fn inputs(&self) -> BTreeSet<String> {
+        self.cases
+            .iter()
+            .flat_map(|(cond, expr)| cond.free_vars().into_iter().chain(expr.free_vars()))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CoverageOptions {
+    /// Largest difference between the two sides of a boundary that still
+    /// counts as continuous.
+    pub tolerance: f64,
+    /// Also compare first derivatives at every boundary.
+    pub derivatives: bool,
+}
+
+impl Default for CoverageOptions {
+    fn default() -> Self {
+        CoverageOptions {
+            tolerance: 1e-9,
+            derivatives: false,
+        }
+    }
+}
+
+/// A point where the input moves from one region of a piecewise function to
+/// the next.
+#[derive(Clone, Debug)]
+pub struct Boundary {
+    pub at: f64,
+    /// The value of the expression governing the region to the left, and to
+    /// the right, evaluated at the boundary.
+    pub left: f64,
+    pub right: f64,
+    pub continuous: bool,
+    /// Whether the two sides' first derivatives agree, when requested.
+    pub derivative_continuous: Option<bool>,
+}
+
+#[derive(Clone)]
+pub struct CoverageReport {
+    /// The stretches between interval cases, where `otherwise` applies.
+    pub gaps: Vec<Interval<f64>>,
+    /// Every boundary between consecutive regions, left to right, counting
+    /// the gaps and unbounded tails as regions of their own.
+    pub boundaries: Vec<Boundary>,
+    /// Whether any input falls through to the `otherwise` case.
+    pub otherwise_reachable: bool,
+}
+
+impl CoverageReport {
+    pub fn continuous(&self) -> bool {
+        self.boundaries.iter().all(|b| b.continuous)
+    }
+}
+
+impl Piecewise<f64> {
+    /// Where the interval cases leave gaps and whether neighbouring regions
+    /// agree where they meet. Fails unless the function is well formed and
+    /// every case but `otherwise` is a bare interval.
+    pub fn coverage_report(&self, opts: &CoverageOptions) -> Result<CoverageReport> {
+        let regions = self.partition()?;
+        let otherwise = &*self.cases[self.cases.len() - 1].1;
+
+        let boundaries = regions
+            .windows(2)
+            .map(|w| {
+                let (at, l, r) = (w[0].0.high_val, w[0].1, w[1].1);
+                let (left, dl) = l.eval_with_derivative(at);
+                let (right, dr) = r.eval_with_derivative(at);
+                Boundary {
+                    at,
+                    left,
+                    right,
+                    continuous: (left - right).abs() <= opts.tolerance,
+                    derivative_continuous: opts
+                        .derivatives
+                        .then(|| (dl - dr).abs() <= opts.tolerance),
+                }
+            })
+            .collect();
+
+        Ok(CoverageReport {
+            otherwise_reachable: regions
+                .iter()
+                .any(|(_, expr)| std::ptr::eq(*expr, otherwise)),
+            gaps: self.gaps(),
+            boundaries,
+        })
+    }
+}
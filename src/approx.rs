@@ -0,0 +1,525 @@
+use crate::ast::{CaseCondition, Expr, Interval, Openness, Piecewise};
+use anyhow::{anyhow, Result};
+use std::f64::consts::PI;
+
+/// Number of evenly spaced points used to measure approximation error and
+/// to search for error extrema during the Remez exchange.
+const ERROR_SAMPLES: usize = 4096;
+const REMEZ_ITERATIONS: usize = 40;
+/// Points checked inside each segment when adaptively refining a
+/// piecewise-linear approximation, and the deepest it will split.
+const SEGMENT_SAMPLES: usize = 16;
+const MAX_SPLIT_DEPTH: usize = 30;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ApproxMethod {
+    /// Interpolation at Chebyshev nodes: near-minimax and cheap to compute.
+    Chebyshev,
+    /// Remez exchange, converging on the true minimax polynomial.
+    Remez,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Segmentation {
+    /// This many segments of equal width.
+    Uniform(usize),
+    /// Split segments in half until none deviates from the expression by more
+    /// than `max_error` at its sample points.
+    Adaptive { max_error: f64 },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interpolation {
+    /// Each entry holds the value at its midpoint.
+    Nearest,
+    /// Each entry interpolates linearly between its endpoints.
+    Linear,
+}
+
+#[derive(Clone)]
+pub struct Approximation {
+    pub expr: Expr<f64>,
+    pub max_abs_error: f64,
+    /// Measured only where the approximated function is non-zero.
+    pub max_rel_error: f64,
+}
+
+/// Maps `[-1, 1]` onto an interval and back; fits are computed in `t` for
+/// numerical stability and only converted to the caller's variable at the
+/// end.
+struct Domain {
+    low: f64,
+    high: f64,
+}
+
+impl Domain {
+    fn new(interval: &Interval<f64>) -> Result<Self> {
+        let (low, high) = (interval.low_val, interval.high_val);
+        if low >= high || !low.is_finite() || !high.is_finite() {
+            return Err(anyhow!(
+                "Cannot approximate over {}: interval must be finite and non-empty",
+                interval
+            ));
+        }
+        Ok(Domain { low, high })
+    }
+
+    fn x(&self, t: f64) -> f64 {
+        (self.high - self.low) / 2.0 * t + (self.high + self.low) / 2.0
+    }
+
+    /// Rewrite coefficients of a polynomial in `t` as coefficients in `x`,
+    /// substituting `t = alpha * x + beta`.
+    fn to_x(&self, coeffs: &[f64]) -> Vec<f64> {
+        let alpha = 2.0 / (self.high - self.low);
+        let beta = -(self.high + self.low) / (self.high - self.low);
+        // Horner over polynomials: acc = acc * (alpha x + beta) + c
+        let mut acc = vec![0.0; coeffs.len()];
+        for &c in coeffs.iter().rev() {
+            let mut next = vec![0.0; coeffs.len()];
+            for (k, &a) in acc.iter().enumerate() {
+                next[k] += a * beta;
+                if k + 1 < next.len() {
+                    next[k + 1] += a * alpha;
+                }
+            }
+            next[0] += c;
+            acc = next;
+        }
+        acc
+    }
+}
+
+fn poly_eval(coeffs: &[f64], t: f64) -> f64 {
+    coeffs.iter().rev().fold(0.0, |acc, &c| acc * t + c)
+}
+
+/// Horner form `c0 + x * (c1 + x * (...))`, which is what `to_rust` and the
+/// fixed-point evaluator handle best.
+fn horner(coeffs: &[f64], var: &str) -> Expr<f64> {
+    let x = || Box::new(Expr::Var(var.to_string()));
+    let mut iter = coeffs.iter().rev();
+    let last = *iter.next().unwrap_or(&0.0);
+    iter.fold(Expr::Const(last), |acc, &c| {
+        Expr::Add(
+            Box::new(Expr::Const(c)),
+            Box::new(Expr::Mul(x(), Box::new(acc))),
+        )
+    })
+    .simplify()
+}
+
+/// Solve `a * x = b` by Gaussian elimination with partial pivoting.
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Result<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+            .unwrap();
+        if a[pivot][col].abs() < 1e-300 {
+            return Err(anyhow!("Cannot approximate: singular system while fitting"));
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (v, p) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *v -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Ok(x)
+}
+
+/// Evaluate `f` at `t` in `[-1, 1]`, rejecting non-finite values.
+fn sample(f: &Expr<f64>, domain: &Domain, t: f64) -> Result<f64> {
+    let x = domain.x(t);
+    let y = f.eval(x);
+    if !y.is_finite() {
+        return Err(anyhow!("Cannot approximate {}: not finite at {}", f, x));
+    }
+    Ok(y)
+}
+
+fn grid() -> impl Iterator<Item = f64> {
+    (0..=ERROR_SAMPLES).map(|i| -1.0 + 2.0 * i as f64 / ERROR_SAMPLES as f64)
+}
+
+/// Pick `count` points of alternating error sign, taking the largest error
+/// in each run of constant sign and trimming the smaller end until the
+/// right number remain. Returns `None` if there are too few sign changes.
+fn alternating_extrema(err: &dyn Fn(f64) -> f64, count: usize) -> Option<Vec<f64>> {
+    let mut extrema: Vec<(f64, f64)> = Vec::new();
+    for t in grid() {
+        let e = err(t);
+        match extrema.last_mut() {
+            Some(last) if last.1.signum() == e.signum() => {
+                if e.abs() > last.1.abs() {
+                    *last = (t, e);
+                }
+            }
+            _ => extrema.push((t, e)),
+        }
+    }
+    while extrema.len() > count {
+        if extrema[0].1.abs() < extrema[extrema.len() - 1].1.abs() {
+            extrema.remove(0);
+        } else {
+            extrema.pop();
+        }
+    }
+    (extrema.len() == count).then(|| extrema.into_iter().map(|(t, _)| t).collect())
+}
+
+fn chebyshev_nodes(count: usize) -> Vec<f64> {
+    (0..count)
+        .map(|k| (PI * (k as f64 + 0.5) / count as f64).cos())
+        .collect()
+}
+
+fn chebyshev_extrema(count: usize) -> Vec<f64> {
+    (0..count)
+        .map(|k| -(PI * k as f64 / (count - 1) as f64).cos())
+        .collect()
+}
+
+fn measure(f: &Expr<f64>, expr: Expr<f64>, domain: &Domain) -> Approximation {
+    let (mut max_abs_error, mut max_rel_error) = (0.0f64, 0.0f64);
+    for t in grid() {
+        let x = domain.x(t);
+        let (exact, approx) = (f.eval(x), expr.eval(x));
+        let e = (exact - approx).abs();
+        max_abs_error = max_abs_error.max(e);
+        if exact != 0.0 {
+            max_rel_error = max_rel_error.max(e / exact.abs());
+        }
+    }
+    Approximation {
+        expr,
+        max_abs_error,
+        max_rel_error,
+    }
+}
+
+fn chebyshev_fit(f: &Expr<f64>, domain: &Domain, degree: usize) -> Result<Vec<f64>> {
+    let nodes = chebyshev_nodes(degree + 1);
+    let a = nodes
+        .iter()
+        .map(|&t| (0..=degree).map(|j| t.powi(j as i32)).collect())
+        .collect();
+    let b = nodes
+        .iter()
+        .map(|&t| sample(f, domain, t))
+        .collect::<Result<_>>()?;
+    solve_linear(a, b)
+}
+
+fn remez_fit(f: &Expr<f64>, domain: &Domain, degree: usize) -> Result<Vec<f64>> {
+    let mut reference = chebyshev_extrema(degree + 2);
+    let mut coeffs = chebyshev_fit(f, domain, degree)?;
+    for _ in 0..REMEZ_ITERATIONS {
+        // p(t_i) + (-1)^i E = f(t_i), unknowns are the coefficients and E
+        let a = reference
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| {
+                let mut row: Vec<f64> = (0..=degree).map(|j| t.powi(j as i32)).collect();
+                row.push(if i % 2 == 0 { 1.0 } else { -1.0 });
+                row
+            })
+            .collect();
+        let b = reference
+            .iter()
+            .map(|&t| sample(f, domain, t))
+            .collect::<Result<_>>()?;
+        let mut solution = solve_linear(a, b)?;
+        let level = solution.pop().unwrap().abs();
+        coeffs = solution;
+
+        let err = |t: f64| f.eval(domain.x(t)) - poly_eval(&coeffs, t);
+        let max = grid().map(|t| err(t).abs()).fold(0.0, f64::max);
+        if max - level <= 1e-9 * max.max(f64::MIN_POSITIVE) {
+            break;
+        }
+        match alternating_extrema(&err, degree + 2) {
+            Some(next) => reference = next,
+            None => break,
+        }
+    }
+    Ok(coeffs)
+}
+
+/// Rational Remez: solves the linearised system
+/// `p(t_i) - (f(t_i) - (-1)^i E') * (q(t_i) - 1) + (-1)^i E = f(t_i)`
+/// with `q_0 = 1`, re-using the previous levelled error `E'` until it
+/// settles, then exchanges the reference points as for polynomials.
+fn rational_remez_fit(
+    f: &Expr<f64>,
+    domain: &Domain,
+    num_degree: usize,
+    den_degree: usize,
+) -> Result<(Vec<f64>, Vec<f64>)> {
+    let unknowns = num_degree + den_degree + 2;
+    let mut reference = chebyshev_extrema(unknowns);
+    let mut num = chebyshev_fit(f, domain, num_degree)?;
+    let mut den = vec![1.0];
+    let mut level = 0.0;
+    for _ in 0..REMEZ_ITERATIONS {
+        let fs = reference
+            .iter()
+            .map(|&t| sample(f, domain, t))
+            .collect::<Result<Vec<_>>>()?;
+        for _ in 0..REMEZ_ITERATIONS {
+            let a = reference
+                .iter()
+                .zip(&fs)
+                .enumerate()
+                .map(|(i, (&t, &y))| {
+                    let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    let mut row: Vec<f64> = (0..=num_degree).map(|j| t.powi(j as i32)).collect();
+                    row.extend((1..=den_degree).map(|k| -(y - sign * level) * t.powi(k as i32)));
+                    row.push(sign);
+                    row
+                })
+                .collect();
+            let mut solution = solve_linear(a, fs.clone())?;
+            let next_level = solution.pop().unwrap();
+            den = std::iter::once(1.0)
+                .chain(solution.drain(num_degree + 1..))
+                .collect();
+            num = solution;
+            let settled = (next_level - level).abs() <= 1e-12 * next_level.abs().max(1e-300);
+            level = next_level;
+            if settled {
+                break;
+            }
+        }
+
+        if grid().any(|t| poly_eval(&den, t) <= 0.0) {
+            return Err(anyhow!(
+                "Cannot approximate {}: rational fit has a pole in the interval",
+                f
+            ));
+        }
+        let err = |t: f64| f.eval(domain.x(t)) - poly_eval(&num, t) / poly_eval(&den, t);
+        let max = grid().map(|t| err(t).abs()).fold(0.0, f64::max);
+        if max - level.abs() <= 1e-9 * max.max(f64::MIN_POSITIVE) {
+            break;
+        }
+        match alternating_extrema(&err, unknowns) {
+            Some(next) => reference = next,
+            None => break,
+        }
+    }
+    Ok((num, den))
+}
+
+/// Fail unless `var` is the only variable `expr` reads. Approximations
+/// sample `eval`, which binds every variable to its argument, so any other
+/// variable would silently stand for `var` as well.
+fn check_single_var(expr: &Expr<f64>, var: &str) -> Result<()> {
+    match expr.free_vars().into_iter().find(|v| v != var) {
+        Some(other) => Err(anyhow!(
+            "Cannot approximate {} in \"{}\": it also depends on \"{}\"",
+            expr,
+            var,
+            other
+        )),
+        None => Ok(()),
+    }
+}
+
+impl Expr<f64> {
+    /// A polynomial of the given degree in `var` approximating `self` over
+    /// `interval`, in Horner form, together with its measured error.
+    pub fn approximate_polynomial(
+        &self,
+        var: &str,
+        interval: &Interval<f64>,
+        degree: usize,
+        method: ApproxMethod,
+    ) -> Result<Approximation> {
+        check_single_var(self, var)?;
+        let domain = Domain::new(interval)?;
+        let coeffs = match method {
+            ApproxMethod::Chebyshev => chebyshev_fit(self, &domain, degree)?,
+            ApproxMethod::Remez => remez_fit(self, &domain, degree)?,
+        };
+        Ok(measure(self, horner(&domain.to_x(&coeffs), var), &domain))
+    }
+
+    /// A minimax rational function `p(var) / q(var)` with the given numerator
+    /// and denominator degrees, found by the rational Remez algorithm.
+    pub fn approximate_rational(
+        &self,
+        var: &str,
+        interval: &Interval<f64>,
+        num_degree: usize,
+        den_degree: usize,
+    ) -> Result<Approximation> {
+        check_single_var(self, var)?;
+        let domain = Domain::new(interval)?;
+        let (num, den) = rational_remez_fit(self, &domain, num_degree, den_degree)?;
+        let expr = Expr::Div(
+            Box::new(horner(&domain.to_x(&num), var)),
+            Box::new(horner(&domain.to_x(&den), var)),
+        );
+        Ok(measure(self, expr, &domain))
+    }
+}
+
+/// The line through `(x0, y0)` and `(x1, y1)` as `slope * var + intercept`.
+fn line(var: &str, x0: f64, y0: f64, x1: f64, y1: f64) -> Expr<f64> {
+    let slope = (y1 - y0) / (x1 - x0);
+    let intercept = y0 - slope * x0;
+    Expr::Add(
+        Box::new(Expr::Mul(
+            Box::new(Expr::Const(slope)),
+            Box::new(Expr::Var(var.to_string())),
+        )),
+        Box::new(Expr::Const(intercept)),
+    )
+    .simplify()
+}
+
+impl Expr<f64> {
+    fn finite_at(&self, x: f64) -> Result<f64> {
+        let y = self.eval(x);
+        if !y.is_finite() {
+            return Err(anyhow!("Cannot tabulate {}: not finite at {}", self, x));
+        }
+        Ok(y)
+    }
+
+    /// Breakpoints for `segments` over `[low, high]`, always including both
+    /// ends, for segments that are lines in `var`.
+    fn breakpoints(
+        &self,
+        var: &str,
+        low: f64,
+        high: f64,
+        segments: Segmentation,
+    ) -> Result<Vec<f64>> {
+        match segments {
+            Segmentation::Uniform(n) => {
+                let n = n.max(1);
+                Ok((0..=n)
+                    .map(|i| low + (high - low) * i as f64 / n as f64)
+                    .collect())
+            }
+            Segmentation::Adaptive { max_error } => {
+                let mut points = vec![low];
+                self.refine(var, low, high, max_error, MAX_SPLIT_DEPTH, &mut points)?;
+                Ok(points)
+            }
+        }
+    }
+
+    fn refine(
+        &self,
+        var: &str,
+        a: f64,
+        b: f64,
+        max_error: f64,
+        depth: usize,
+        points: &mut Vec<f64>,
+    ) -> Result<()> {
+        let seg = line(var, a, self.finite_at(a)?, b, self.finite_at(b)?);
+        let mut worst = 0.0f64;
+        for k in 1..SEGMENT_SAMPLES {
+            let x = a + (b - a) * k as f64 / SEGMENT_SAMPLES as f64;
+            worst = worst.max((self.finite_at(x)? - seg.eval(x)).abs());
+        }
+        if worst <= max_error {
+            points.push(b);
+            return Ok(());
+        }
+        if depth == 0 {
+            return Err(anyhow!(
+                "Cannot approximate {} within {} on [{}, {}]: segments became too small",
+                self,
+                max_error,
+                a,
+                b
+            ));
+        }
+        let m = a + (b - a) / 2.0;
+        self.refine(var, a, m, max_error, depth - 1, points)?;
+        self.refine(var, m, b, max_error, depth - 1, points)
+    }
+
+    /// Assemble consecutive `[p_i, p_{i+1})` cases, taking the outer
+    /// openness from `interval`, with `self` as the `otherwise` case so the
+    /// result stays exact outside the tabulated range.
+    fn tabulate(
+        &self,
+        lhs: &str,
+        interval: &Interval<f64>,
+        points: &[f64],
+        case: impl Fn(f64, f64) -> Result<Expr<f64>>,
+    ) -> Result<Piecewise<f64>> {
+        let last = points.len() - 2;
+        let mut cases = Vec::with_capacity(points.len());
+        for (i, w) in points.windows(2).enumerate() {
+            let low_openness = if i == 0 {
+                interval.low_openness
+            } else {
+                Openness::Closed
+            };
+            let high_openness = if i == last {
+                interval.high_openness
+            } else {
+                Openness::Open
+            };
+            let bounds = Interval::new(w[0], w[1], low_openness, high_openness);
+            cases.push((CaseCondition::Interval(bounds), Box::new(case(w[0], w[1])?)));
+        }
+        cases.push((CaseCondition::Otherwise, Box::new(self.clone())));
+        Ok(Piecewise::new(lhs.to_string(), cases))
+    }
+
+    /// A `Piecewise` of linear segments in `var` that interpolates `self` at
+    /// every breakpoint of `interval`. Outside `interval` the `otherwise`
+    /// case evaluates `self` exactly.
+    pub fn piecewise_linear(
+        &self,
+        lhs: &str,
+        var: &str,
+        interval: &Interval<f64>,
+        segments: Segmentation,
+    ) -> Result<Piecewise<f64>> {
+        check_single_var(self, var)?;
+        let domain = Domain::new(interval)?;
+        let points = self.breakpoints(var, domain.low, domain.high, segments)?;
+        self.tabulate(lhs, interval, &points, |a, b| {
+            Ok(line(var, a, self.finite_at(a)?, b, self.finite_at(b)?))
+        })
+    }
+
+    /// A dense lookup table of `entries` equal-width cases over `interval`,
+    /// each either a constant or a linear interpolation in `var`.
+    pub fn lookup_table(
+        &self,
+        lhs: &str,
+        var: &str,
+        interval: &Interval<f64>,
+        entries: usize,
+        interpolation: Interpolation,
+    ) -> Result<Piecewise<f64>> {
+        check_single_var(self, var)?;
+        let domain = Domain::new(interval)?;
+        let points =
+            self.breakpoints(var, domain.low, domain.high, Segmentation::Uniform(entries))?;
+        self.tabulate(lhs, interval, &points, |a, b| match interpolation {
+            Interpolation::Nearest => Ok(Expr::Const(self.finite_at(a + (b - a) / 2.0)?)),
+            Interpolation::Linear => Ok(line(var, a, self.finite_at(a)?, b, self.finite_at(b)?)),
+        })
+    }
+}
@@ -4,7 +4,10 @@
 use std::fmt::{self, Display};
 
 /// A valid `Piecewise` function requires that all the intervals in cases are
-/// non-overlapping and ordered, and terminate with an "otherwise" case.
+/// non-overlapping and ordered, and terminate with an "otherwise" case. When
+/// every case is instead a box over named variables (`x in [0, 1) and y in
+/// [0, 2]`), the boxes must not overlap, and may leave out the "otherwise"
+/// case if they tile their bounding hull without gaps.
 #[derive(Clone)]
 pub struct Piecewise<T> {
     pub(crate) lhs: String,
@@ -13,7 +16,6 @@ pub struct Piecewise<T> {
 }
 
 // TODO: Well-formedness check
-// TODO: Ends of range for fixpoint (no explicit infinities)
 impl<T> Piecewise<T> {
     pub fn new(lhs: String, cases: Vec<(CaseCondition<T>, Box<Expr<T>>)>) -> Self {
         Piecewise { lhs, cases }
@@ -25,17 +27,37 @@ impl<T: Display> fmt::Display for Piecewise<T> {
         let eqns = self
             .cases
             .iter()
-            .map(|(interval, expr)| format!("  {} if {}", expr, interval).to_string())
+            .map(|(interval, expr)| format!("{} if {}", expr, interval).to_string())
             .collect::<Vec<_>>()
-            .join("\n");
-        write!(f, "{}{{\n{}\n}}", self.lhs, eqns)
+            .join(", ");
+        write!(f, "{} = {{{}}}", self.lhs, eqns)
     }
 }
 
+/// A case applies when its condition holds. A bare `Interval` tests the
+/// piecewise function's implicit argument; every other condition names the
+/// variables it depends on. `NonZero` holds when its expression is anything
+/// but zero (NaN included), which is how a number is read as a boolean.
 #[derive(Clone, PartialEq)]
 pub enum CaseCondition<T> {
     Otherwise,
     Interval(Interval<T>),
+    In(String, Interval<T>),
+    Compare(CmpOp, Box<Expr<T>>, Box<Expr<T>>),
+    And(Box<CaseCondition<T>>, Box<CaseCondition<T>>),
+    Or(Box<CaseCondition<T>>, Box<CaseCondition<T>>),
+    Not(Box<CaseCondition<T>>),
+    NonZero(Box<Expr<T>>),
+}
+
+impl<T> CaseCondition<T> {
+    /// Read `expr` as a condition, unwrapping a condition's truth value.
+    pub fn from_expr(expr: Expr<T>) -> Self {
+        match expr {
+            Expr::Bool(c) => *c,
+            e => CaseCondition::NonZero(Box::new(e)),
+        }
+    }
 }
 
 impl<T: Display> fmt::Display for CaseCondition<T> {
@@ -44,11 +66,54 @@ impl<T: Display> fmt::Display for CaseCondition<T> {
             CaseCondition::Otherwise => write!(f, "otherwise"),
 
             CaseCondition::Interval(i) => write!(f, "{}", i),
+            CaseCondition::In(v, i) => write!(f, "{} in {}", v, i),
+            CaseCondition::Compare(op, a, b) => write!(f, "{} {} {}", a, op, b),
+            CaseCondition::And(a, b) => write!(f, "({} and {})", a, b),
+            CaseCondition::Or(a, b) => write!(f, "({} or {})", a, b),
+            CaseCondition::Not(c) => write!(f, "not {}", c),
+            CaseCondition::NonZero(e) => write!(f, "{}", e),
         }
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CmpOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl CmpOp {
+    pub fn apply<T: PartialOrd>(self, a: T, b: T) -> bool {
+        match self {
+            CmpOp::Lt => a < b,
+            CmpOp::Le => a <= b,
+            CmpOp::Gt => a > b,
+            CmpOp::Ge => a >= b,
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+        }
+    }
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Interval<T> {
     pub(crate) low_val: T,
     pub(crate) high_val: T,
@@ -67,26 +132,50 @@ impl<T> Interval<T> {
     }
 }
 
+impl<T: PartialOrd> Interval<T> {
+    /// Whether the low end lies above the high end. Unbounded ends never do.
+    pub fn is_inverted(&self) -> bool {
+        self.low_openness != Openness::Unbounded
+            && self.high_openness != Openness::Unbounded
+            && self.low_val > self.high_val
+    }
+}
+
 impl<T: Display> fmt::Display for Interval<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let (left_c, right_c) = match (self.low_openness, self.high_openness) {
-            (Openness::Closed, Openness::Closed) => ("[", "]"),
-            (Openness::Closed, Openness::Open) => ("[", ")"),
-            (Openness::Open, Openness::Closed) => ("(", "]"),
-            (Openness::Open, Openness::Open) => ("(", ")"),
-        };
-        write!(
-            f,
-            "{}{}, {}{}",
-            left_c, self.low_val, self.high_val, right_c
-        )
+        match self.low_openness {
+            Openness::Closed => write!(f, "[{}, ", literal(&self.low_val))?,
+            Openness::Open => write!(f, "({}, ", literal(&self.low_val))?,
+            Openness::Unbounded => write!(f, "(-inf, ")?,
+        }
+        match self.high_openness {
+            Openness::Closed => write!(f, "{}]", literal(&self.high_val)),
+            Openness::Open => write!(f, "{})", literal(&self.high_val)),
+            Openness::Unbounded => write!(f, "inf)"),
+        }
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+/// How an interval treats one of its ends. At an `Unbounded` end the bound
+/// value is ignored and should be the type's extreme: an infinity for `f64`,
+/// the minimum or maximum for fixed-point types.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Openness {
     Open,
     Closed,
+    Unbounded,
+}
+
+impl Openness {
+    /// The openness of the complementary side of a shared endpoint. An
+    /// unbounded end is never shared and stays unbounded.
+    pub fn flip(self) -> Openness {
+        match self {
+            Openness::Open => Openness::Closed,
+            Openness::Closed => Openness::Open,
+            Openness::Unbounded => Openness::Unbounded,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -115,7 +204,7 @@ impl<T: Display> fmt::Display for Equation<T> {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum Expr<T> {
     Const(T),
     Var(String),
@@ -128,25 +217,116 @@ pub enum Expr<T> {
     Neg(Box<Expr<T>>),
     Sin(Box<Expr<T>>),
     Cos(Box<Expr<T>>),
+    Asin(Box<Expr<T>>),
+    Acos(Box<Expr<T>>),
+    Sqrt(Box<Expr<T>>),
+    /// 1 when the condition holds and 0 otherwise. Conditions inside an
+    /// expression name the variables they test, so unlike piecewise
+    /// conditions they never contain a bare `Interval`.
+    Bool(Box<CaseCondition<T>>),
+    /// The first expression when the condition holds, else the second. Only
+    /// the chosen one is evaluated.
+    If(Box<CaseCondition<T>>, Box<Expr<T>>, Box<Expr<T>>),
+    /// `let name = value in body`: the body, with `name` standing for the
+    /// value, which is computed once.
+    Let(String, Box<Expr<T>>, Box<Expr<T>>),
+}
+
+impl<T> Expr<T> {
+    /// The truth value of `cond`, unwrapping an expression read as a
+    /// condition.
+    pub fn from_condition(cond: CaseCondition<T>) -> Box<Expr<T>> {
+        match cond {
+            CaseCondition::NonZero(e) => e,
+            c => Box::new(Expr::Bool(Box::new(c))),
+        }
+    }
+}
+
+/// The values of the `let` names around an expression being evaluated by
+/// `eval`, innermost first. Each frame lives on the stack of the `let` that
+/// binds it.
+pub(crate) struct Scope<'a, T> {
+    pub(crate) name: &'a str,
+    pub(crate) value: T,
+    pub(crate) outer: Option<&'a Scope<'a, T>>,
+}
+
+impl<T: Copy> Scope<'_, T> {
+    /// The value of the innermost binding of `name`, or `arg` when no
+    /// enclosing `let` binds it.
+    pub(crate) fn lookup(mut scope: Option<&Scope<T>>, name: &str, arg: T) -> T {
+        while let Some(frame) = scope {
+            if frame.name == name {
+                return frame.value;
+            }
+            scope = frame.outer;
+        }
+        arg
+    }
 }
 
 // TODO: special constants pi & e (in parse)
 
+/// `c` as text, in exponent form where plain decimal would need a long run
+/// of zeros, as for 1e300 or 2.5e-7. The digits are moved rather than
+/// recomputed, so the text still reads back as exactly `c`.
+fn literal<T: Display>(c: &T) -> String {
+    let s = c.to_string();
+    let (sign, body) = match s.strip_prefix('-') {
+        Some(body) => ("-", body),
+        None => ("", s.as_str()),
+    };
+    let (int, frac) = body.split_once('.').unwrap_or((body, ""));
+    let decimal = |d: &str| d.bytes().all(|b| b.is_ascii_digit());
+    if int.is_empty() || !decimal(int) || !decimal(frac) {
+        return s;
+    }
+    let digits = format!("{}{}", int, frac);
+    let Some(first) = digits.bytes().position(|b| b != b'0') else {
+        return s;
+    };
+    let exponent = int.len() as i64 - 1 - first as i64;
+    if (-5..16).contains(&exponent) {
+        return s;
+    }
+    let significant = digits[first..].trim_end_matches('0');
+    let mantissa = match significant.split_at(1) {
+        (lead, "") => lead.to_string(),
+        (lead, rest) => format!("{}.{}", lead, rest),
+    };
+    format!("{}{}e{}", sign, mantissa, exponent)
+}
+
 impl<T: Display> fmt::Display for Expr<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use Expr::*;
         let s = match self {
-            Const(c) => c.to_string(),
+            Const(c) => literal(c),
             Var(s) => s.to_owned(),
             Add(op1, op2) => format!("({} + {})", op1, op2),
             Sub(op1, op2) => format!("({} - {})", op1, op2),
             Mul(op1, op2) => format!("({} * {})", op1, op2),
             Div(op1, op2) => format!("({} / {})", op1, op2),
-            Pow(op1, op2) => format!("({}^{})", op1, op2),
+            // a signed base needs its own parentheses, since -a^b is -(a^b)
+            Pow(op1, op2) => match op1.to_string() {
+                base if base.starts_with('-') => format!("(({})^{})", base, op2),
+                base => format!("({}^{})", base, op2),
+            },
             Log(op1, op2) => format!("log({}, {})", op1, op2),
             Neg(op) => format!("-{}", op),
             Sin(op) => format!("sin({})", op),
             Cos(op) => format!("cos({})", op),
+            Asin(op) => format!("asin({})", op),
+            Acos(op) => format!("acos({})", op),
+            Sqrt(op) => format!("sqrt({})", op),
+            Bool(c) => match **c {
+                CaseCondition::And(..) | CaseCondition::Or(..) => c.to_string(),
+                CaseCondition::NonZero(ref e) => format!("({} != 0)", e),
+                _ => format!("({})", c),
+            },
+            If(c, a, b) => format!("({} ? {} : {})", c, a, b),
+            Let(name, value, body) => format!("(let {} = {} in {})", name, value, body),
         };
 
         write!(f, "{}", s)
@@ -0,0 +1,562 @@
+use crate::ast::{CaseCondition, Expr, Interval, Openness, Piecewise};
+use crate::solve::polynomial;
+use anyhow::{anyhow, Result};
+use std::f64::consts::E;
+
+fn boxed(e: Expr<f64>) -> Box<Expr<f64>> {
+    Box::new(e)
+}
+
+fn ln(e: Expr<f64>) -> Expr<f64> {
+    Expr::Log(boxed(e), boxed(Expr::Const(E)))
+}
+
+/// `ln(|e|)`, written as `ln(e^2) / 2` for want of an absolute value.
+fn ln_abs(e: Expr<f64>) -> Expr<f64> {
+    use Expr::*;
+    let square = Pow(boxed(e), boxed(Const(2.0)));
+    Div(boxed(ln(square)), boxed(Const(2.0)))
+}
+
+/// Split `expr` into `(a, b)` with `expr = a * var + b`, if it is linear in
+/// `var` with a non-zero slope.
+fn linear(expr: &Expr<f64>, var: &str) -> Option<(Expr<f64>, Expr<f64>)> {
+    let mut coeffs: Vec<_> = polynomial(expr, var)
+        .ok()?
+        .iter()
+        .map(Expr::simplify)
+        .collect();
+    while matches!(coeffs.last(), Some(Expr::Const(0.0))) {
+        coeffs.pop();
+    }
+    match coeffs.len() {
+        2 => {
+            let a = coeffs.pop()?;
+            Some((a, coeffs.pop()?))
+        }
+        _ => None,
+    }
+}
+
+/// The Taylor coefficients `f^(k)(at) / k!` of `expr` in `var`, for
+/// `k <= order`. They are found by arithmetic on truncated power series
+/// rather than by differentiating repeatedly, which would grow the
+/// expression exponentially in `order`; each coefficient is built from the
+/// lower ones and simplified as soon as it is known.
+fn series(expr: &Expr<f64>, var: &str, at: f64, order: usize) -> Vec<Expr<f64>> {
+    use Expr::*;
+    let s = |e: &Expr<f64>| series(e, var, at, order);
+    match expr {
+        Var(v) if v == var => {
+            let mut u = constant(Const(at), order);
+            if order > 0 {
+                u[1] = Const(1.0);
+            }
+            u
+        }
+        Const(_) | Var(_) => constant(expr.clone(), order),
+        Add(op1, op2) => zip(s(op1), s(op2), Add),
+        Sub(op1, op2) => zip(s(op1), s(op2), Sub),
+        Neg(op) => s(op)
+            .into_iter()
+            .map(|c| Neg(boxed(c)).simplify())
+            .collect(),
+        Mul(op1, op2) => series_mul(&s(op1), &s(op2)),
+        Div(op1, op2) => series_div(&s(op1), &s(op2)),
+        Pow(op1, op2) => match op2.simplify() {
+            Const(n) => series_powf(&s(op1), n),
+            // f^g = e^(g ln(f))
+            _ => series_exp(&series_mul(&s(op2), &series_ln(&s(op1)))),
+        },
+        Log(op1, op2) => series_div(&series_ln(&s(op1)), &series_ln(&s(op2))),
+        Sin(op) => series_sin_cos(&s(op)).0,
+        Cos(op) => series_sin_cos(&s(op)).1,
+        Asin(op) => series_asin(&s(op)),
+        Acos(op) => {
+            // acos(u) = pi / 2 - asin(u)
+            let u = s(op);
+            let mut y: Vec<_> = series_asin(&u)
+                .into_iter()
+                .map(|c| Neg(boxed(c)).simplify())
+                .collect();
+            y[0] = Acos(boxed(u[0].clone())).simplify();
+            y
+        }
+        Sqrt(op) => series_powf(&s(op), 0.5),
+        // a condition is constant near `at` unless it changes there, in which
+        // case the expansion follows the side that holds at `at` itself
+        Bool(_) => constant(expr.substitute(var, &Const(at)).simplify(), order),
+        If(cond, a, b) => {
+            let holds = Bool(cond.clone()).substitute(var, &Const(at)).simplify();
+            match holds {
+                Const(h) if h != 0.0 => s(a),
+                Const(_) => s(b),
+                holds => {
+                    let cond = CaseCondition::from_expr(holds);
+                    zip(s(a), s(b), |a, b| If(Box::new(cond.clone()), a, b))
+                }
+            }
+        }
+        Let(..) => s(&expr.inline_lets()),
+    }
+}
+
+fn constant(c: Expr<f64>, order: usize) -> Vec<Expr<f64>> {
+    let mut u = vec![Expr::Const(0.0); order + 1];
+    u[0] = c;
+    u
+}
+
+fn zip(
+    a: Vec<Expr<f64>>,
+    b: Vec<Expr<f64>>,
+    op: impl Fn(Box<Expr<f64>>, Box<Expr<f64>>) -> Expr<f64>,
+) -> Vec<Expr<f64>> {
+    a.into_iter()
+        .zip(b)
+        .map(|(a, b)| op(boxed(a), boxed(b)).simplify())
+        .collect()
+}
+
+/// `terms` added up, or 0 if there are none.
+fn sum(terms: impl Iterator<Item = Expr<f64>>) -> Expr<f64> {
+    terms
+        .reduce(|acc, t| Expr::Add(boxed(acc), boxed(t)))
+        .unwrap_or(Expr::Const(0.0))
+}
+
+/// `c * a * b`, skipping the factor `c` when it is 1.
+fn scaled(c: f64, a: &Expr<f64>, b: &Expr<f64>) -> Expr<f64> {
+    let ab = Expr::Mul(boxed(a.clone()), boxed(b.clone()));
+    match c {
+        1.0 => ab,
+        _ => Expr::Mul(boxed(Expr::Const(c)), boxed(ab)),
+    }
+}
+
+fn series_mul(a: &[Expr<f64>], b: &[Expr<f64>]) -> Vec<Expr<f64>> {
+    (0..a.len())
+        .map(|k| sum((0..=k).map(|i| scaled(1.0, &a[i], &b[k - i]))).simplify())
+        .collect()
+}
+
+fn series_div(a: &[Expr<f64>], b: &[Expr<f64>]) -> Vec<Expr<f64>> {
+    let mut q: Vec<Expr<f64>> = Vec::with_capacity(a.len());
+    for k in 0..a.len() {
+        let known = sum((1..=k).map(|i| scaled(1.0, &b[i], &q[k - i])));
+        let rest = Expr::Sub(boxed(a[k].clone()), boxed(known));
+        q.push(Expr::Div(boxed(rest), boxed(b[0].clone())).simplify());
+    }
+    q
+}
+
+/// `u^n`, by repeated multiplication for small whole `n`, which also
+/// works where `u` vanishes, and by the recurrence for `u' p = n u p'`
+/// otherwise.
+fn series_powf(u: &[Expr<f64>], n: f64) -> Vec<Expr<f64>> {
+    if n.fract() == 0.0 && (0.0..=16.0).contains(&n) {
+        return (0..n as usize).fold(constant(Expr::Const(1.0), u.len() - 1), |p, _| {
+            series_mul(&p, u)
+        });
+    }
+    let mut p = vec![Expr::Pow(boxed(u[0].clone()), boxed(Expr::Const(n))).simplify()];
+    for k in 1..u.len() {
+        let terms = (1..=k).map(|i| scaled(n * i as f64 - (k - i) as f64, &u[i], &p[k - i]));
+        let divisor = Expr::Mul(boxed(Expr::Const(k as f64)), boxed(u[0].clone()));
+        p.push(Expr::Div(boxed(sum(terms)), boxed(divisor)).simplify());
+    }
+    p
+}
+
+/// `e^u`, from `e' = u' e`.
+fn series_exp(u: &[Expr<f64>]) -> Vec<Expr<f64>> {
+    let mut e = vec![Expr::Pow(boxed(Expr::Const(E)), boxed(u[0].clone())).simplify()];
+    for k in 1..u.len() {
+        let terms = (1..=k).map(|i| scaled(i as f64 / k as f64, &u[i], &e[k - i]));
+        e.push(sum(terms).simplify());
+    }
+    e
+}
+
+/// `ln(u)`, from `u l' = u'`.
+fn series_ln(u: &[Expr<f64>]) -> Vec<Expr<f64>> {
+    let mut l = vec![ln(u[0].clone()).simplify()];
+    for k in 1..u.len() {
+        let known = sum((1..k).map(|i| scaled(i as f64 / k as f64, &l[i], &u[k - i])));
+        let rest = Expr::Sub(boxed(u[k].clone()), boxed(known));
+        l.push(Expr::Div(boxed(rest), boxed(u[0].clone())).simplify());
+    }
+    l
+}
+
+/// `sin(u)` and `cos(u)`, which are each other's derivatives up to sign.
+fn series_sin_cos(u: &[Expr<f64>]) -> (Vec<Expr<f64>>, Vec<Expr<f64>>) {
+    let mut sin = vec![Expr::Sin(boxed(u[0].clone())).simplify()];
+    let mut cos = vec![Expr::Cos(boxed(u[0].clone())).simplify()];
+    for k in 1..u.len() {
+        let weight = |i: usize| i as f64 / k as f64;
+        let s = sum((1..=k).map(|i| scaled(weight(i), &u[i], &cos[k - i])));
+        let c = sum((1..=k).map(|i| scaled(-weight(i), &u[i], &sin[k - i])));
+        sin.push(s.simplify());
+        cos.push(c.simplify());
+    }
+    (sin, cos)
+}
+
+/// `asin(u)`, by integrating `u' / (1 - u^2)^(1/2)`.
+fn series_asin(u: &[Expr<f64>]) -> Vec<Expr<f64>> {
+    let one_minus_sq = zip(
+        constant(Expr::Const(1.0), u.len() - 1),
+        series_mul(u, u),
+        Expr::Sub,
+    );
+    let du: Vec<_> = (1..u.len())
+        .map(|k| Expr::Mul(boxed(Expr::Const(k as f64)), boxed(u[k].clone())).simplify())
+        .collect();
+    let dy = series_mul(&du, &series_powf(&one_minus_sq, -0.5));
+    let mut y = vec![Expr::Asin(boxed(u[0].clone())).simplify()];
+    y.extend(
+        dy.into_iter()
+            .enumerate()
+            .map(|(k, c)| Expr::Div(boxed(c), boxed(Expr::Const((k + 1) as f64))).simplify()),
+    );
+    y
+}
+
+impl Expr<f64> {
+    /// The symbolic partial derivative with respect to `var`.
+    pub fn derivative(&self, var: &str) -> Expr<f64> {
+        self.derive(var).simplify()
+    }
+
+    fn derive(&self, var: &str) -> Expr<f64> {
+        use Expr::*;
+        let d = |e: &Expr<f64>| boxed(e.derive(var));
+        let c = |e: &Expr<f64>| boxed(e.clone());
+        match self {
+            Const(_) => Const(0.0),
+            Var(s) => Const(if s == var { 1.0 } else { 0.0 }),
+            Add(op1, op2) => Add(d(op1), d(op2)),
+            Sub(op1, op2) => Sub(d(op1), d(op2)),
+            Mul(op1, op2) => Add(boxed(Mul(d(op1), c(op2))), boxed(Mul(c(op1), d(op2)))),
+            Div(op1, op2) => Div(
+                boxed(Sub(boxed(Mul(d(op1), c(op2))), boxed(Mul(c(op1), d(op2))))),
+                boxed(Pow(c(op2), boxed(Const(2.0)))),
+            ),
+            Pow(op1, op2) if !op2.contains_var(var) => {
+                // power rule: g * f^(g - 1) * f'
+                let exp = Sub(c(op2), boxed(Const(1.0)));
+                Mul(boxed(Mul(c(op2), boxed(Pow(c(op1), boxed(exp))))), d(op1))
+            }
+            Pow(op1, op2) => {
+                // f^g * (g' * ln(f) + g * f' / f)
+                let inner = Add(
+                    boxed(Mul(d(op2), boxed(ln(*c(op1))))),
+                    boxed(Div(boxed(Mul(c(op2), d(op1))), c(op1))),
+                );
+                Mul(c(self), boxed(inner))
+            }
+            Log(op1, op2) if !op2.contains_var(var) => {
+                // f' / (f * ln(b))
+                Div(d(op1), boxed(Mul(c(op1), boxed(ln(*c(op2))))))
+            }
+            Log(op1, op2) => {
+                // log_b(f) = ln(f) / ln(b), by the quotient rule
+                let (ln_f, ln_b) = (ln(*c(op1)), ln(*c(op2)));
+                let num = Sub(
+                    boxed(Mul(boxed(Div(d(op1), c(op1))), boxed(ln_b.clone()))),
+                    boxed(Mul(boxed(ln_f), boxed(Div(d(op2), c(op2))))),
+                );
+                Div(boxed(num), boxed(Pow(boxed(ln_b), boxed(Const(2.0)))))
+            }
+            Neg(op) => Neg(d(op)),
+            Sin(op) => Mul(boxed(Cos(c(op))), d(op)),
+            Cos(op) => Neg(boxed(Mul(boxed(Sin(c(op))), d(op)))),
+            Asin(op) | Acos(op) => {
+                // d/dx asin(f) = f' / sqrt(1 - f^2), acos is its negation
+                let root = Pow(
+                    boxed(Sub(boxed(Const(1.0)), boxed(Pow(c(op), boxed(Const(2.0)))))),
+                    boxed(Const(0.5)),
+                );
+                let de = Div(d(op), boxed(root));
+                match self {
+                    Asin(_) => de,
+                    _ => Neg(boxed(de)),
+                }
+            }
+            Sqrt(op) => Div(d(op), boxed(Mul(boxed(Const(2.0)), boxed(Sqrt(c(op)))))),
+            // a truth value is flat wherever it does not jump
+            Bool(_) => Const(0.0),
+            If(cond, a, b) => If(cond.clone(), d(a), d(b)),
+            Let(name, value, body) => {
+                // the body depends on var directly and through the bound name
+                let through = Mul(boxed(body.derive(name)), d(value));
+                Let(name.clone(), c(value), boxed(Add(d(body), boxed(through))))
+            }
+        }
+    }
+
+    /// An antiderivative with respect to `var`, with integration constant 0.
+    ///
+    /// Supports polynomials, linearity over `Add`/`Sub`/`Neg` and products or
+    /// quotients by constants, and `sin`, `cos`, exponentials `c^u`, powers
+    /// `u^n`, `1 / u` and `log(u, c)` where `u` is linear in `var`. `1 / u`
+    /// integrates to `ln(|u|)`, so the result holds on either side of `u = 0`.
+    pub fn antiderivative(&self, var: &str) -> Result<Expr<f64>> {
+        Ok(self.integral(var)?.simplify())
+    }
+
+    fn integral(&self, var: &str) -> Result<Expr<f64>> {
+        use Expr::*;
+        let x = || boxed(Var(var.to_string()));
+        let c = |e: &Expr<f64>| boxed(e.clone());
+        if !self.contains_var(var) {
+            return Ok(Mul(c(self), x()));
+        }
+        if let Ok(coeffs) = polynomial(self, var) {
+            // integrate term by term: c_k * x^(k + 1) / (k + 1)
+            return Ok(coeffs
+                .into_iter()
+                .enumerate()
+                .map(|(k, coeff)| {
+                    let n = boxed(Const((k + 1) as f64));
+                    Mul(boxed(coeff), boxed(Div(boxed(Pow(x(), n.clone())), n)))
+                })
+                .reduce(|acc, term| Add(boxed(acc), boxed(term)))
+                .unwrap_or(Const(0.0)));
+        }
+
+        let has = |e: &Expr<f64>| e.contains_var(var);
+        let unsupported = || anyhow!("Cannot integrate {} with respect to \"{}\"", self, var);
+        match self {
+            Let(..) => self.inline_lets().integral(var),
+            Add(op1, op2) => Ok(Add(boxed(op1.integral(var)?), boxed(op2.integral(var)?))),
+            Sub(op1, op2) => Ok(Sub(boxed(op1.integral(var)?), boxed(op2.integral(var)?))),
+            Neg(op) => Ok(Neg(boxed(op.integral(var)?))),
+            Mul(op1, op2) if !has(op1) => Ok(Mul(c(op1), boxed(op2.integral(var)?))),
+            Mul(op1, op2) if !has(op2) => Ok(Mul(boxed(op1.integral(var)?), c(op2))),
+            Div(op1, op2) if !has(op2) => Ok(Div(boxed(op1.integral(var)?), c(op2))),
+            Div(op1, op2) if !has(op1) => {
+                // c / (a x + b) -> c * ln(|a x + b|) / a
+                let (a, _) = linear(op2, var).ok_or_else(unsupported)?;
+                Ok(Div(boxed(Mul(c(op1), boxed(ln_abs(*c(op2))))), boxed(a)))
+            }
+            Pow(op1, op2) if !has(op2) => {
+                let (a, _) = linear(op1, var).ok_or_else(unsupported)?;
+                // a written `-1` is a negated constant until simplified
+                if let Const(n) = op2.simplify() {
+                    if n == -1.0 {
+                        return Ok(Div(boxed(ln_abs(*c(op1))), boxed(a)));
+                    }
+                }
+                let n1 = boxed(Add(c(op2), boxed(Const(1.0))));
+                Ok(Div(
+                    boxed(Pow(c(op1), n1.clone())),
+                    boxed(Mul(n1, boxed(a))),
+                ))
+            }
+            Pow(op1, op2) if !has(op1) => {
+                // c^(a x + b) -> c^(a x + b) / (a ln c)
+                let (a, _) = linear(op2, var).ok_or_else(unsupported)?;
+                Ok(Div(c(self), boxed(Mul(boxed(a), boxed(ln(*c(op1)))))))
+            }
+            Sin(op) => {
+                let (a, _) = linear(op, var).ok_or_else(unsupported)?;
+                Ok(Neg(boxed(Div(boxed(Cos(c(op))), boxed(a)))))
+            }
+            Cos(op) => {
+                let (a, _) = linear(op, var).ok_or_else(unsupported)?;
+                Ok(Div(boxed(Sin(c(op))), boxed(a)))
+            }
+            Sqrt(op) => {
+                // sqrt(a x + b) -> 2 (a x + b)^1.5 / (3 a)
+                let (a, _) = linear(op, var).ok_or_else(unsupported)?;
+                let cube = Pow(c(op), boxed(Const(1.5)));
+                Ok(Div(
+                    boxed(Mul(boxed(Const(2.0)), boxed(cube))),
+                    boxed(Mul(boxed(Const(3.0)), boxed(a))),
+                ))
+            }
+            Log(op1, op2) if !has(op2) => {
+                // log_b(u) = ln(u) / ln(b), and u ln(u) - u integrates ln(u)
+                let (a, _) = linear(op1, var).ok_or_else(unsupported)?;
+                let u_ln_u = Sub(boxed(Mul(c(op1), boxed(ln(*c(op1))))), c(op1));
+                Ok(Div(boxed(u_ln_u), boxed(Mul(boxed(a), boxed(ln(*c(op2)))))))
+            }
+            _ => Err(unsupported()),
+        }
+    }
+
+    /// The Taylor polynomial of degree `order` in `var` around `around`:
+    /// the sum of `f^(k)(around) / k! * (var - around)^k` for `k <= order`.
+    ///
+    /// Any other variables are kept symbolic in the coefficients. Fails if
+    /// a derivative is not finite at `around`, e.g. `log(x, 2)` around 0.
+    pub fn taylor(&self, var: &str, around: f64, order: usize) -> Result<Expr<f64>> {
+        use Expr::*;
+        let basis = match around {
+            0.0 => Var(var.to_string()),
+            _ => Sub(boxed(Var(var.to_string())), boxed(Const(around))),
+        };
+
+        let mut terms = Vec::with_capacity(order + 1);
+        for (k, coeff) in series(self, var, around, order).into_iter().enumerate() {
+            if let Const(c) = coeff {
+                if !c.is_finite() {
+                    return Err(anyhow!(
+                        "Cannot expand {} around {} = {}: derivative {} is not finite",
+                        self,
+                        var,
+                        around,
+                        k
+                    ));
+                }
+            }
+            let power = Pow(boxed(basis.clone()), boxed(Const(k as f64)));
+            terms.push(Mul(boxed(coeff), boxed(power)));
+        }
+        Ok(terms
+            .into_iter()
+            .reduce(|acc, term| Add(boxed(acc), boxed(term)))
+            .unwrap_or(Const(0.0))
+            .simplify())
+    }
+
+    /// The Taylor polynomial around 0.
+    pub fn maclaurin(&self, var: &str, order: usize) -> Result<Expr<f64>> {
+        self.taylor(var, 0.0, order)
+    }
+
+    /// Forward-mode automatic differentiation. Like `eval`, every variable
+    /// is bound to `arg`; returns the value and its derivative at `arg`.
+    pub fn eval_with_derivative(&self, arg: f64) -> (f64, f64) {
+        use Expr::*;
+        match self {
+            Const(c) => (*c, 0.0),
+            Var(_s) => (arg, 1.0),
+            Add(op1, op2) => {
+                let ((a, da), (b, db)) =
+                    (op1.eval_with_derivative(arg), op2.eval_with_derivative(arg));
+                (a + b, da + db)
+            }
+            Sub(op1, op2) => {
+                let ((a, da), (b, db)) =
+                    (op1.eval_with_derivative(arg), op2.eval_with_derivative(arg));
+                (a - b, da - db)
+            }
+            Mul(op1, op2) => {
+                let ((a, da), (b, db)) =
+                    (op1.eval_with_derivative(arg), op2.eval_with_derivative(arg));
+                (a * b, da * b + a * db)
+            }
+            Div(op1, op2) => {
+                let ((a, da), (b, db)) =
+                    (op1.eval_with_derivative(arg), op2.eval_with_derivative(arg));
+                (a / b, (da * b - a * db) / (b * b))
+            }
+            Pow(op1, op2) => {
+                let ((a, da), (b, db)) =
+                    (op1.eval_with_derivative(arg), op2.eval_with_derivative(arg));
+                let v = a.powf(b);
+                let dv = if db == 0.0 {
+                    b * a.powf(b - 1.0) * da
+                } else {
+                    v * (db * a.ln() + b * da / a)
+                };
+                (v, dv)
+            }
+            Log(op1, op2) => {
+                let ((a, da), (b, db)) =
+                    (op1.eval_with_derivative(arg), op2.eval_with_derivative(arg));
+                let (ln_a, ln_b) = (a.ln(), b.ln());
+                (ln_a / ln_b, (da / a * ln_b - ln_a * db / b) / (ln_b * ln_b))
+            }
+            Neg(op) => {
+                let (a, da) = op.eval_with_derivative(arg);
+                (-a, -da)
+            }
+            Sin(op) => {
+                let (a, da) = op.eval_with_derivative(arg);
+                (a.sin(), a.cos() * da)
+            }
+            Cos(op) => {
+                let (a, da) = op.eval_with_derivative(arg);
+                (a.cos(), -a.sin() * da)
+            }
+            Asin(op) => {
+                let (a, da) = op.eval_with_derivative(arg);
+                (a.asin(), da / (1.0 - a * a).sqrt())
+            }
+            Acos(op) => {
+                let (a, da) = op.eval_with_derivative(arg);
+                (a.acos(), -da / (1.0 - a * a).sqrt())
+            }
+            Sqrt(op) => {
+                let (a, da) = op.eval_with_derivative(arg);
+                (a.sqrt(), da / (2.0 * a.sqrt()))
+            }
+            Bool(_) => (self.eval(arg), 0.0),
+            If(cond, a, b) => {
+                if cond.holds(arg) {
+                    a.eval_with_derivative(arg)
+                } else {
+                    b.eval_with_derivative(arg)
+                }
+            }
+            Let(..) => self.inline_lets().eval_with_derivative(arg),
+        }
+    }
+}
+
+impl Piecewise<f64> {
+    /// An antiderivative of every case with respect to `var`, with constants
+    /// chosen so that the result is continuous across every boundary.
+    ///
+    /// The `otherwise` case applies in several disjoint regions, each of
+    /// which needs its own constant, so the result spells out the gaps
+    /// between intervals and the region to the right of the last interval
+    /// as explicit cases. Its `otherwise` case is the leftmost region, whose
+    /// integration constant is 0.
+    pub fn antiderivative(&self, var: &str) -> Result<Piecewise<f64>> {
+        self.well_formed()?;
+        self.require_intervals("integrate")?;
+        let otherwise = self.cases[self.cases.len() - 1].1.antiderivative(var)?;
+
+        // every region in left-to-right order, with its unadjusted antiderivative
+        let mut regions = Vec::new();
+        let mut gaps = self.gaps().into_iter().peekable();
+        for (cond, expr) in &self.cases[..self.cases.len() - 1] {
+            if let CaseCondition::Interval(i) = cond {
+                while let Some(g) = gaps.next_if(|g| g.high_val <= i.low_val) {
+                    regions.push((g, otherwise.clone()));
+                }
+                regions.push((i.clone(), expr.antiderivative(var)?));
+            }
+        }
+        if let Some((last, _)) = regions.last() {
+            if last.high_openness != Openness::Unbounded {
+                let tail = Interval::above(last.high_val, last.high_openness.flip());
+                regions.push((tail, otherwise.clone()));
+            }
+        }
+
+        let mut cases = Vec::new();
+        let (mut prev, mut offset) = (&otherwise, 0.0);
+        for (interval, anti) in &regions {
+            // match the previous region's value at the shared boundary; a
+            // region unbounded on the left has no boundary to match
+            if interval.low_openness != Openness::Unbounded {
+                let p = interval.low_val;
+                offset += prev.eval(p) - anti.eval(p);
+            }
+            let shifted = Expr::Add(boxed(anti.clone()), boxed(Expr::Const(offset)));
+            cases.push((
+                CaseCondition::Interval(interval.clone()),
+                boxed(shifted.simplify()),
+            ));
+            prev = anti;
+        }
+        cases.push((CaseCondition::Otherwise, boxed(otherwise)));
+        Ok(Piecewise::new(self.lhs.clone(), cases))
+    }
+}
@@ -0,0 +1,67 @@
+use crate::ast::{CaseCondition, Expr, Interval, Openness, Piecewise};
+use anyhow::Result;
+
+/// A well formed piecewise function whose interval cases are searched by
+/// bisection instead of one at a time, so that selecting a case among `n`
+/// takes `O(log n)` comparisons.
+#[derive(Clone)]
+pub struct CompiledPiecewise {
+    lhs: String,
+    /// The interval cases in increasing order, which `well_formed` guarantees
+    /// is also the order they were written in.
+    intervals: Vec<Interval<f64>>,
+    exprs: Vec<Expr<f64>>,
+    otherwise: Expr<f64>,
+}
+
+/// Whether `x` is at or beyond the low end of `i`; among sorted,
+/// non-overlapping intervals this is true for a prefix of them.
+fn starts_by(i: &Interval<f64>, x: f64) -> bool {
+    match i.low_openness {
+        Openness::Open => i.low_val < x,
+        Openness::Closed => i.low_val <= x,
+        Openness::Unbounded => !x.is_nan(),
+    }
+}
+
+impl Piecewise<f64> {
+    /// Fails unless the function is well formed and every case but
+    /// `otherwise` is a bare interval.
+    pub fn compile(&self) -> Result<CompiledPiecewise> {
+        self.well_formed()?;
+        self.require_intervals("compile")?;
+        let (intervals, exprs) = self
+            .cases
+            .iter()
+            .filter_map(|(cond, expr)| match cond {
+                CaseCondition::Interval(i) => Some((i.clone(), (**expr).clone())),
+                _ => None,
+            })
+            .unzip();
+        Ok(CompiledPiecewise {
+            lhs: self.lhs.clone(),
+            intervals,
+            exprs,
+            otherwise: (*self.cases[self.cases.len() - 1].1).clone(),
+        })
+    }
+}
+
+impl CompiledPiecewise {
+    pub fn lhs(&self) -> &str {
+        &self.lhs
+    }
+
+    /// The same case `Piecewise::case_at` selects.
+    pub fn case_at(&self, arg: f64) -> &Expr<f64> {
+        let k = self.intervals.partition_point(|i| starts_by(i, arg));
+        match k.checked_sub(1) {
+            Some(k) if self.intervals[k].contains(arg) => &self.exprs[k],
+            _ => &self.otherwise,
+        }
+    }
+
+    pub fn eval(&self, arg: f64) -> f64 {
+        self.case_at(arg).eval(arg)
+    }
+}
@@ -0,0 +1,571 @@
+//! Common-subexpression elimination.
+//!
+//! An expression is hash-consed into a `Dag` in which every distinct subtree
+//! is a single node, so `sin(x) * sin(x)` multiplies one `sin(x)` node by
+//! itself. Let bindings disappear along the way, since a bound name is just
+//! another reference to its value's node.
+//!
+//! Evaluating a `Dag` computes each node at most once, and only when it is
+//! needed: the branches of a conditional and the right side of `and` and
+//! `or` are still evaluated lazily. `to_expr` turns a `Dag` back into an
+//! expression that binds every reused node to a temporary with `let`, which
+//! is what `to_rust` generates code from.
+
+use crate::ast::{CaseCondition, CmpOp, Expr, Interval};
+use crate::fixed_point::CordicMarker;
+use crate::subst::within;
+use crate::util::{cordic_log, cordic_powf};
+use anyhow::{anyhow, Result};
+use cordic::CordicNumber;
+use fixed::types::extra::{LeEqU16, LeEqU32, LeEqU64, LeEqU8};
+use std::collections::HashMap;
+
+/// Constants that are the same exactly when their bits are, so that nodes
+/// can be hashed. Distinguishes `0.0` from `-0.0`, which divide differently.
+pub trait ConstBits {
+    fn bits(&self) -> u64;
+}
+
+impl ConstBits for f64 {
+    fn bits(&self) -> u64 {
+        self.to_bits()
+    }
+}
+
+impl<Frac: LeEqU8> ConstBits for fixed::FixedI8<Frac> {
+    fn bits(&self) -> u64 {
+        self.to_bits() as u64
+    }
+}
+
+impl<Frac: LeEqU16> ConstBits for fixed::FixedI16<Frac> {
+    fn bits(&self) -> u64 {
+        self.to_bits() as u64
+    }
+}
+
+impl<Frac: LeEqU32> ConstBits for fixed::FixedI32<Frac> {
+    fn bits(&self) -> u64 {
+        self.to_bits() as u64
+    }
+}
+
+impl<Frac: LeEqU64> ConstBits for fixed::FixedI64<Frac> {
+    fn bits(&self) -> u64 {
+        self.to_bits() as u64
+    }
+}
+
+/// One node of a `Dag`, referring to its operands by index. Conditions are
+/// nodes too, whose value is 1 when they hold and 0 otherwise, and any
+/// nonzero value is read as true.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Node<T> {
+    Const(T),
+    Var(String),
+    Add(usize, usize),
+    Sub(usize, usize),
+    Mul(usize, usize),
+    Div(usize, usize),
+    Pow(usize, usize),
+    Log(usize, usize),
+    Neg(usize),
+    Sin(usize),
+    Cos(usize),
+    Asin(usize),
+    Acos(usize),
+    Sqrt(usize),
+    Compare(CmpOp, usize, usize),
+    In(usize, Interval<T>),
+    NonZero(usize),
+    Not(usize),
+    /// The second operand is only evaluated when the first holds.
+    And(usize, usize),
+    /// The second operand is only evaluated when the first does not hold.
+    Or(usize, usize),
+    /// Only the branch the condition selects is evaluated.
+    If(usize, usize, usize),
+}
+
+impl<T> Node<T> {
+    /// The operands computed whenever this node is, followed by those that
+    /// are computed only depending on their values.
+    fn operands(&self) -> (Vec<usize>, Vec<usize>) {
+        use Node::*;
+        match *self {
+            Const(_) | Var(_) => (vec![], vec![]),
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b) | Pow(a, b) | Log(a, b) => {
+                (vec![a, b], vec![])
+            }
+            Compare(_, a, b) => (vec![a, b], vec![]),
+            Neg(a) | Sin(a) | Cos(a) | Asin(a) | Acos(a) | Sqrt(a) => (vec![a], vec![]),
+            In(a, _) | NonZero(a) | Not(a) => (vec![a], vec![]),
+            And(a, b) | Or(a, b) => (vec![a], vec![b]),
+            If(c, a, b) => (vec![c], vec![a, b]),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        matches!(self, Node::Const(_) | Node::Var(_))
+    }
+
+    /// Whether the value is always 0 or 1.
+    fn is_condition(&self) -> bool {
+        use Node::*;
+        matches!(
+            self,
+            Compare(..) | In(..) | NonZero(_) | Not(_) | And(..) | Or(..)
+        )
+    }
+}
+
+impl<T: ConstBits> Node<T> {
+    /// The node with its constants replaced by their bits, for hashing.
+    fn key(&self) -> Node<u64> {
+        use Node::*;
+        match self {
+            Const(c) => Const(c.bits()),
+            Var(s) => Var(s.clone()),
+            Add(a, b) => Add(*a, *b),
+            Sub(a, b) => Sub(*a, *b),
+            Mul(a, b) => Mul(*a, *b),
+            Div(a, b) => Div(*a, *b),
+            Pow(a, b) => Pow(*a, *b),
+            Log(a, b) => Log(*a, *b),
+            Neg(a) => Neg(*a),
+            Sin(a) => Sin(*a),
+            Cos(a) => Cos(*a),
+            Asin(a) => Asin(*a),
+            Acos(a) => Acos(*a),
+            Sqrt(a) => Sqrt(*a),
+            Compare(op, a, b) => Compare(*op, *a, *b),
+            In(a, i) => In(
+                *a,
+                Interval::new(
+                    i.low_val.bits(),
+                    i.high_val.bits(),
+                    i.low_openness,
+                    i.high_openness,
+                ),
+            ),
+            NonZero(a) => NonZero(*a),
+            Not(a) => Not(*a),
+            And(a, b) => And(*a, *b),
+            Or(a, b) => Or(*a, *b),
+            If(c, a, b) => If(*c, *a, *b),
+        }
+    }
+}
+
+/// An expression with every repeated subtree stored once. Operands always
+/// come before the nodes that use them, so the nodes are in evaluation order.
+#[derive(Clone)]
+pub struct Dag<T> {
+    nodes: Vec<Node<T>>,
+    root: usize,
+}
+
+struct Builder<T> {
+    nodes: Vec<Node<T>>,
+    index: HashMap<Node<u64>, usize>,
+}
+
+impl<T: Clone + ConstBits> Builder<T> {
+    /// The index of `node`, which is only added if no equal node exists.
+    fn add(&mut self, node: Node<T>) -> usize {
+        let key = node.key();
+        if let Some(&i) = self.index.get(&key) {
+            return i;
+        }
+        self.nodes.push(node);
+        self.index.insert(key, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    /// `scope` maps each name bound by an enclosing `let` to its value.
+    fn expr(&mut self, e: &Expr<T>, scope: &HashMap<String, usize>) -> Result<usize> {
+        use Expr::*;
+        let node = match e {
+            Const(c) => Node::Const(c.clone()),
+            Var(s) => match scope.get(s) {
+                Some(&i) => return Ok(i),
+                None => Node::Var(s.clone()),
+            },
+            Add(a, b) => Node::Add(self.expr(a, scope)?, self.expr(b, scope)?),
+            Sub(a, b) => Node::Sub(self.expr(a, scope)?, self.expr(b, scope)?),
+            Mul(a, b) => Node::Mul(self.expr(a, scope)?, self.expr(b, scope)?),
+            Div(a, b) => Node::Div(self.expr(a, scope)?, self.expr(b, scope)?),
+            Pow(a, b) => Node::Pow(self.expr(a, scope)?, self.expr(b, scope)?),
+            Log(a, b) => Node::Log(self.expr(a, scope)?, self.expr(b, scope)?),
+            Neg(a) => Node::Neg(self.expr(a, scope)?),
+            Sin(a) => Node::Sin(self.expr(a, scope)?),
+            Cos(a) => Node::Cos(self.expr(a, scope)?),
+            Asin(a) => Node::Asin(self.expr(a, scope)?),
+            Acos(a) => Node::Acos(self.expr(a, scope)?),
+            Sqrt(a) => Node::Sqrt(self.expr(a, scope)?),
+            Bool(c) => {
+                let c = self.condition(c, scope)?;
+                if self.nodes[c].is_condition() {
+                    return Ok(c);
+                }
+                Node::NonZero(c)
+            }
+            If(c, a, b) => Node::If(
+                self.condition(c, scope)?,
+                self.expr(a, scope)?,
+                self.expr(b, scope)?,
+            ),
+            Let(name, value, body) => {
+                let value = self.expr(value, scope)?;
+                let mut scope = scope.clone();
+                scope.insert(name.clone(), value);
+                return self.expr(body, &scope);
+            }
+        };
+        Ok(self.add(node))
+    }
+
+    /// A node that is nonzero exactly when `c` holds.
+    fn condition(&mut self, c: &CaseCondition<T>, scope: &HashMap<String, usize>) -> Result<usize> {
+        use CaseCondition::*;
+        let node = match c {
+            Otherwise | Interval(_) => {
+                return Err(anyhow!(
+                    "\"{}\" can only be the condition of a piecewise case",
+                    match c {
+                        Otherwise => "otherwise",
+                        _ => "bare interval",
+                    }
+                ))
+            }
+            In(v, i) => Node::In(self.expr(&Expr::Var(v.clone()), scope)?, i.clone()),
+            Compare(op, a, b) => Node::Compare(*op, self.expr(a, scope)?, self.expr(b, scope)?),
+            And(a, b) => Node::And(self.condition(a, scope)?, self.condition(b, scope)?),
+            Or(a, b) => Node::Or(self.condition(a, scope)?, self.condition(b, scope)?),
+            Not(a) => Node::Not(self.condition(a, scope)?),
+            NonZero(e) => return self.expr(e, scope),
+        };
+        Ok(self.add(node))
+    }
+}
+
+impl<T: Clone + ConstBits> Expr<T> {
+    /// Fails on `otherwise` or a bare interval inside a condition, neither of
+    /// which the parser produces.
+    pub fn to_dag(&self) -> Result<Dag<T>> {
+        let mut builder = Builder {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+        };
+        let root = builder.expr(self, &HashMap::new())?;
+        Ok(Dag {
+            nodes: builder.nodes,
+            root,
+        })
+    }
+
+    /// The expression with every repeated subexpression computed once and
+    /// bound to a temporary, as described in `Dag::to_expr`.
+    pub fn eliminate_common_subexprs(&self) -> Result<Expr<T>> {
+        Ok(self.to_dag()?.to_expr())
+    }
+}
+
+impl<T> Dag<T> {
+    pub fn nodes(&self) -> &[Node<T>] {
+        &self.nodes
+    }
+
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    /// The nodes other than constants and variables that more than one node
+    /// uses, in evaluation order.
+    pub fn shared(&self) -> Vec<usize> {
+        let mut uses = vec![0; self.nodes.len()];
+        for node in &self.nodes {
+            let (eager, lazy) = node.operands();
+            for i in eager.into_iter().chain(lazy) {
+                uses[i] += 1;
+            }
+        }
+        (0..self.nodes.len())
+            .filter(|&i| uses[i] > 1 && !self.nodes[i].is_leaf())
+            .collect()
+    }
+
+    /// Which nodes `root` depends on, itself included.
+    fn reachable(&self, root: usize) -> Vec<bool> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut stack = vec![root];
+        while let Some(i) = stack.pop() {
+            if !seen[i] {
+                seen[i] = true;
+                let (eager, lazy) = self.nodes[i].operands();
+                stack.extend(eager.into_iter().chain(lazy));
+            }
+        }
+        seen
+    }
+}
+
+impl<T: Clone> Dag<T> {
+    /// The expression with each node that is used more than once bound to a
+    /// temporary by `let`. A node is bound where it is evaluated
+    /// unconditionally, so that conditionals never compute a temporary that
+    /// only an untaken branch needs; a node used only in both branches of a
+    /// conditional is therefore computed in each of them. Temporaries are
+    /// named `t` followed by the node's index, with more `t`s if that could
+    /// clash with a variable.
+    pub fn to_expr(&self) -> Expr<T> {
+        let mut prefix = String::from("t");
+        while self.nodes.iter().any(|node| match node {
+            Node::Var(s) => s
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())),
+            _ => false,
+        }) {
+            prefix.push('t');
+        }
+        self.region(self.root, &prefix, &mut vec![None; self.nodes.len()])
+    }
+
+    /// The expression for `root` and everything evaluated whenever it is.
+    /// `bound` holds the temporaries of the enclosing regions.
+    fn region(&self, root: usize, prefix: &str, bound: &mut Vec<Option<String>>) -> Expr<T> {
+        let mut uses = vec![0; self.nodes.len()];
+        let mut nested = Vec::new();
+        let mut stack = vec![root];
+        while let Some(i) = stack.pop() {
+            uses[i] += 1;
+            if uses[i] == 1 {
+                let (eager, lazy) = self.nodes[i].operands();
+                stack.extend(eager);
+                nested.extend(lazy);
+            }
+        }
+        nested.sort_unstable();
+        nested.dedup();
+        // a node is also reused when a lazily evaluated operand needs it
+        for r in nested {
+            for (i, needed) in self.reachable(r).into_iter().enumerate() {
+                if needed && uses[i] > 0 {
+                    uses[i] += 1;
+                }
+            }
+        }
+        let temps: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| uses[i] > 1 && bound[i].is_none() && !self.nodes[i].is_leaf())
+            .collect();
+        let values: Vec<(String, Expr<T>)> = temps
+            .iter()
+            .map(|&i| {
+                let value = self.node_expr(i, prefix, bound);
+                let name = format!("{}{}", prefix, i);
+                bound[i] = Some(name.clone());
+                (name, value)
+            })
+            .collect();
+        let body = self.reference(root, prefix, bound);
+        for &i in &temps {
+            bound[i] = None;
+        }
+        values.into_iter().rev().fold(body, |body, (name, value)| {
+            Expr::Let(name, Box::new(value), Box::new(body))
+        })
+    }
+
+    /// The temporary holding node `i`, or else its expression.
+    fn reference(&self, i: usize, prefix: &str, bound: &mut Vec<Option<String>>) -> Expr<T> {
+        match &bound[i] {
+            Some(name) => Expr::Var(name.clone()),
+            None => self.node_expr(i, prefix, bound),
+        }
+    }
+
+    fn node_expr(&self, i: usize, prefix: &str, bound: &mut Vec<Option<String>>) -> Expr<T> {
+        let mut op = |k: usize| Box::new(self.reference(k, prefix, bound));
+        let cond = |e: Box<Expr<T>>| Box::new(CaseCondition::from_expr(*e));
+        match &self.nodes[i] {
+            Node::Const(c) => Expr::Const(c.clone()),
+            Node::Var(s) => Expr::Var(s.clone()),
+            Node::Add(a, b) => Expr::Add(op(*a), op(*b)),
+            Node::Sub(a, b) => Expr::Sub(op(*a), op(*b)),
+            Node::Mul(a, b) => Expr::Mul(op(*a), op(*b)),
+            Node::Div(a, b) => Expr::Div(op(*a), op(*b)),
+            Node::Pow(a, b) => Expr::Pow(op(*a), op(*b)),
+            Node::Log(a, b) => Expr::Log(op(*a), op(*b)),
+            Node::Neg(a) => Expr::Neg(op(*a)),
+            Node::Sin(a) => Expr::Sin(op(*a)),
+            Node::Cos(a) => Expr::Cos(op(*a)),
+            Node::Asin(a) => Expr::Asin(op(*a)),
+            Node::Acos(a) => Expr::Acos(op(*a)),
+            Node::Sqrt(a) => Expr::Sqrt(op(*a)),
+            Node::Compare(cmp, a, b) => {
+                let (a, b) = (op(*a), op(*b));
+                Expr::Bool(Box::new(CaseCondition::Compare(*cmp, a, b)))
+            }
+            Node::In(a, interval) => Expr::Bool(Box::new(match *op(*a) {
+                Expr::Var(v) => CaseCondition::In(v, interval.clone()),
+                e => within(&e, interval),
+            })),
+            Node::NonZero(a) => Expr::Bool(Box::new(CaseCondition::NonZero(op(*a)))),
+            Node::Not(a) => Expr::Bool(Box::new(CaseCondition::Not(cond(op(*a))))),
+            Node::And(a, b) => {
+                let a = cond(op(*a));
+                let b = cond(Box::new(self.region(*b, prefix, bound)));
+                Expr::Bool(Box::new(CaseCondition::And(a, b)))
+            }
+            Node::Or(a, b) => {
+                let a = cond(op(*a));
+                let b = cond(Box::new(self.region(*b, prefix, bound)));
+                Expr::Bool(Box::new(CaseCondition::Or(a, b)))
+            }
+            Node::If(c, a, b) => {
+                let c = cond(op(*c));
+                Expr::If(
+                    c,
+                    Box::new(self.region(*a, prefix, bound)),
+                    Box::new(self.region(*b, prefix, bound)),
+                )
+            }
+        }
+    }
+}
+
+impl Dag<f64> {
+    pub fn eval(&self, arg: f64) -> f64 {
+        let mut values = vec![None; self.nodes.len()];
+        self.value(self.root, &|_| Ok(arg), &mut values)
+            .expect("every variable is bound to the argument")
+    }
+
+    /// Evaluate with each variable looked up by name in `env`, failing on
+    /// any variable that is not bound.
+    pub fn eval_with(&self, env: &HashMap<String, f64>) -> Result<f64> {
+        let var = |s: &str| {
+            env.get(s)
+                .copied()
+                .ok_or_else(|| anyhow!("Unbound variable \"{}\"", s))
+        };
+        self.value(self.root, &var, &mut vec![None; self.nodes.len()])
+    }
+
+    /// Code that computes each temporary of `to_expr` once.
+    pub fn to_rust(&self) -> String {
+        // a dag has no bare intervals, which `to_dag` rejects
+        self.to_expr().rust(None)
+    }
+
+    /// The value of node `i`, computed at most once.
+    fn value(
+        &self,
+        i: usize,
+        var: &dyn Fn(&str) -> Result<f64>,
+        values: &mut [Option<f64>],
+    ) -> Result<f64> {
+        use Node::*;
+        if let Some(v) = values[i] {
+            return Ok(v);
+        }
+        let mut op = |k: usize| self.value(k, var, values);
+        let truth = |b: bool| if b { 1.0 } else { 0.0 };
+        let v = match &self.nodes[i] {
+            Const(c) => *c,
+            Var(s) => var(s)?,
+            Add(a, b) => op(*a)? + op(*b)?,
+            Sub(a, b) => op(*a)? - op(*b)?,
+            Mul(a, b) => op(*a)? * op(*b)?,
+            Div(a, b) => op(*a)? / op(*b)?,
+            Pow(a, b) => op(*a)?.powf(op(*b)?),
+            Log(a, b) => op(*a)?.log(op(*b)?),
+            Neg(a) => -op(*a)?,
+            Sin(a) => op(*a)?.sin(),
+            Cos(a) => op(*a)?.cos(),
+            Asin(a) => op(*a)?.asin(),
+            Acos(a) => op(*a)?.acos(),
+            Sqrt(a) => op(*a)?.sqrt(),
+            Compare(cmp, a, b) => truth(cmp.apply(op(*a)?, op(*b)?)),
+            In(a, interval) => truth(interval.contains(op(*a)?)),
+            NonZero(a) => truth(op(*a)? != 0.0),
+            Not(a) => truth(op(*a)? == 0.0),
+            And(a, b) => truth(op(*a)? != 0.0 && op(*b)? != 0.0),
+            Or(a, b) => truth(op(*a)? != 0.0 || op(*b)? != 0.0),
+            If(c, a, b) => {
+                if op(*c)? != 0.0 {
+                    op(*a)?
+                } else {
+                    op(*b)?
+                }
+            }
+        };
+        values[i] = Some(v);
+        Ok(v)
+    }
+}
+
+impl<T: CordicNumber + CordicMarker> Dag<T> {
+    pub fn eval(&self, arg: T) -> T {
+        let mut values = vec![None; self.nodes.len()];
+        self.value(self.root, &|_| Ok(arg), &mut values)
+            .expect("every variable is bound to the argument")
+    }
+
+    /// Evaluate with each variable looked up by name in `env`, failing on
+    /// any variable that is not bound.
+    pub fn eval_with(&self, env: &HashMap<String, T>) -> Result<T> {
+        let var = |s: &str| {
+            env.get(s)
+                .copied()
+                .ok_or_else(|| anyhow!("Unbound variable \"{}\"", s))
+        };
+        self.value(self.root, &var, &mut vec![None; self.nodes.len()])
+    }
+
+    /// The value of node `i`, computed at most once.
+    fn value(
+        &self,
+        i: usize,
+        var: &dyn Fn(&str) -> Result<T>,
+        values: &mut [Option<T>],
+    ) -> Result<T> {
+        use Node::*;
+        if let Some(v) = values[i] {
+            return Ok(v);
+        }
+        let mut op = |k: usize| self.value(k, var, values);
+        let truth = |b: bool| if b { T::one() } else { T::zero() };
+        let v = match &self.nodes[i] {
+            Const(c) => *c,
+            Var(s) => var(s)?,
+            Add(a, b) => op(*a)? + op(*b)?,
+            Sub(a, b) => op(*a)? - op(*b)?,
+            Mul(a, b) => op(*a)? * op(*b)?,
+            Div(a, b) => op(*a)? / op(*b)?,
+            Pow(a, b) => cordic_powf(op(*a)?, op(*b)?),
+            Log(a, b) => cordic_log(op(*a)?, op(*b)?),
+            Neg(a) => -op(*a)?,
+            Sin(a) => cordic::sin(op(*a)?),
+            Cos(a) => cordic::cos(op(*a)?),
+            Asin(a) => cordic::asin(op(*a)?),
+            Acos(a) => cordic::acos(op(*a)?),
+            Sqrt(a) => cordic::sqrt(op(*a)?),
+            Compare(cmp, a, b) => truth(cmp.apply(op(*a)?, op(*b)?)),
+            In(a, interval) => truth(interval.contains(op(*a)?)),
+            NonZero(a) => truth(op(*a)? != T::zero()),
+            Not(a) => truth(op(*a)? == T::zero()),
+            And(a, b) => truth(op(*a)? != T::zero() && op(*b)? != T::zero()),
+            Or(a, b) => truth(op(*a)? != T::zero() || op(*b)? != T::zero()),
+            If(c, a, b) => {
+                if op(*c)? != T::zero() {
+                    op(*a)?
+                } else {
+                    op(*b)?
+                }
+            }
+        };
+        values[i] = Some(v);
+        Ok(v)
+    }
+}
@@ -1,7 +1,9 @@
-use fixed::{types::extra::LeEqU64, FixedI64};
-use crate::ast::{Equation, Expr};
+use crate::ast::{CaseCondition, Expr, Interval, Openness, Piecewise, Scope};
+use anyhow::{anyhow, Result};
 use cordic::CordicNumber;
-//use util::{cordic_log, cordic_powf};
+use fixed::traits::Fixed;
+use std::fmt::Display;
+use crate::util::{cordic_log, cordic_powf};
 
 pub trait CordicMarker {}
 impl<Frac> CordicMarker for fixed::FixedI8<Frac> {}
@@ -9,21 +11,247 @@ impl<Frac> CordicMarker for fixed::FixedI16<Frac> {}
 impl<Frac> CordicMarker for fixed::FixedI32<Frac> {}
 impl<Frac> CordicMarker for fixed::FixedI64<Frac> {}
 
+impl Expr<f64> {
+    /// Convert every constant to the fixed-point type `T`, rounding to the
+    /// nearest representable value and saturating at its bounds.
+    pub fn to_fixed<T: Fixed>(&self) -> Expr<T> {
+        self.map_consts(&|c| T::saturating_from_num(*c))
+    }
+}
+
+/// Convert one interval bound to `T`. An unbounded end moves to the type's
+/// minimum or maximum; a bound beyond the type's range, including an infinite
+/// one, saturates there and becomes closed, so that the extreme value itself
+/// stays inside the interval.
+fn saturate<T: Fixed>(bound: f64, openness: Openness) -> (T, Openness) {
+    if openness == Openness::Unbounded {
+        let extreme = if bound < 0.0 { T::MIN } else { T::MAX };
+        (extreme, Openness::Unbounded)
+    } else if bound < T::MIN.to_num::<f64>() {
+        (T::MIN, Openness::Closed)
+    } else if bound > T::MAX.to_num::<f64>() {
+        (T::MAX, Openness::Closed)
+    } else {
+        (T::saturating_from_num(bound), openness)
+    }
+}
+
+impl Interval<f64> {
+    pub fn to_fixed<T: Fixed>(&self) -> Interval<T> {
+        let (low_val, low_openness) = saturate(self.low_val, self.low_openness);
+        let (high_val, high_openness) = saturate(self.high_val, self.high_openness);
+        Interval::new(low_val, high_val, low_openness, high_openness)
+    }
+}
+
+impl CaseCondition<f64> {
+    pub fn to_fixed<T: Fixed>(&self) -> CaseCondition<T> {
+        use CaseCondition::*;
+        match self {
+            Otherwise => Otherwise,
+            Interval(i) => Interval(i.to_fixed()),
+            In(v, i) => In(v.clone(), i.to_fixed()),
+            Compare(op, a, b) => Compare(*op, Box::new(a.to_fixed()), Box::new(b.to_fixed())),
+            And(a, b) => And(Box::new(a.to_fixed()), Box::new(b.to_fixed())),
+            Or(a, b) => Or(Box::new(a.to_fixed()), Box::new(b.to_fixed())),
+            Not(c) => Not(Box::new(c.to_fixed())),
+            NonZero(e) => NonZero(Box::new(e.to_fixed())),
+        }
+    }
+
+    fn bounds(&self) -> Vec<f64> {
+        use CaseCondition::*;
+        match self {
+            Otherwise | Compare(..) | NonZero(_) => vec![],
+            Interval(i) | In(_, i) => vec![i.low_val, i.high_val],
+            And(a, b) | Or(a, b) => [a.bounds(), b.bounds()].concat(),
+            Not(c) => c.bounds(),
+        }
+    }
+}
+
+impl Piecewise<f64> {
+    /// Convert every constant and interval bound to `T`, rounding to the
+    /// nearest representable value and saturating at the type's range.
+    pub fn to_fixed<T: Fixed>(&self) -> Piecewise<T> {
+        let cases = self
+            .cases
+            .iter()
+            .map(|(cond, expr)| (cond.to_fixed(), Box::new(expr.to_fixed())))
+            .collect();
+        Piecewise::new(self.lhs.clone(), cases)
+    }
+
+    /// Fails if any finite interval bound would be rounded or saturated by
+    /// `to_fixed::<T>()`, which could move a case boundary. Infinite bounds
+    /// are expected to saturate and are always accepted.
+    pub fn check_representable<T: Fixed>(&self) -> Result<()> {
+        for (cond, _) in &self.cases {
+            for bound in cond.bounds().into_iter().filter(|b| b.is_finite()) {
+                let exact = T::checked_from_num(bound).is_some_and(|t| t.to_num::<f64>() == bound);
+                if !exact {
+                    return Err(anyhow!(
+                        "Interval bound {} in {} is not exactly representable in the fixed-point format",
+                        bound,
+                        cond
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: CordicNumber + CordicMarker> Interval<T> {
+    pub fn contains(&self, x: T) -> bool {
+        let above = match self.low_openness {
+            Openness::Open => x > self.low_val,
+            Openness::Closed => x >= self.low_val,
+            Openness::Unbounded => true,
+        };
+        let below = match self.high_openness {
+            Openness::Open => x < self.high_val,
+            Openness::Closed => x <= self.high_val,
+            Openness::Unbounded => true,
+        };
+        above && below
+    }
+}
+
+impl<T: CordicNumber + CordicMarker> CaseCondition<T> {
+    /// Whether the condition holds when every variable, and the implicit
+    /// argument of bare intervals, is bound to `arg`.
+    pub fn holds(&self, arg: T) -> bool {
+        self.holds_in(arg, None)
+    }
+
+    fn holds_in(&self, arg: T, scope: Option<&Scope<T>>) -> bool {
+        use CaseCondition::*;
+        match self {
+            Otherwise => true,
+            Interval(i) => i.contains(arg),
+            In(v, i) => i.contains(Scope::lookup(scope, v, arg)),
+            Compare(op, a, b) => op.apply(a.eval_in(arg, scope), b.eval_in(arg, scope)),
+            And(a, b) => a.holds_in(arg, scope) && b.holds_in(arg, scope),
+            Or(a, b) => a.holds_in(arg, scope) || b.holds_in(arg, scope),
+            Not(c) => !c.holds_in(arg, scope),
+            NonZero(e) => e.eval_in(arg, scope) != T::zero(),
+        }
+    }
+}
+
+impl<T: CordicNumber + CordicMarker + Display> Piecewise<T> {
+    /// The same checks as the floating-point `well_formed`, except for boxes
+    /// over several variables: bare intervals must be ordered and must not
+    /// overlap, and only the last case may be, and must be, `otherwise`.
+    pub fn well_formed(&self) -> Result<()> {
+        if self.cases.is_empty() {
+            return Err(anyhow!("Malformed piecewise function: missing any cases"));
+        }
+
+        let mut last: Option<&Interval<T>> = None;
+        for (cond, _) in &self.cases[..self.cases.len() - 1] {
+            match cond {
+                CaseCondition::Otherwise => {
+                    return Err(anyhow!("Malformed piecewise function: \"otherwise\" should only be the last piecewise case"));
+                }
+                CaseCondition::Interval(i) => {
+                    if i.is_inverted() {
+                        return Err(anyhow!(
+                            "Malformed piecewise function: inverted interval {}",
+                            i
+                        ));
+                    }
+                    if let Some(prev) = last {
+                        let after = i.low_openness != Openness::Unbounded
+                            && prev.high_openness != Openness::Unbounded
+                            && (i.low_val > prev.high_val
+                                || (i.low_val == prev.high_val
+                                    && (i.low_openness == Openness::Open
+                                        || prev.high_openness == Openness::Open)));
+                        if !after {
+                            return Err(anyhow!(
+                                "Malformed piecewise function: overlapping interval {}",
+                                i
+                            ));
+                        }
+                    }
+                    last = Some(i);
+                }
+                _ => {}
+            }
+        }
+
+        if self.cases[self.cases.len() - 1].0 != CaseCondition::Otherwise {
+            return Err(anyhow!(
+                "Malformed piecewise function: the last cases should always be \"otherwise\""
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<T: CordicNumber + CordicMarker> Piecewise<T> {
+    pub fn eval(&self, arg: T) -> T {
+        self.case_at(arg).eval(arg)
+    }
+
+    /// The expression of the first case whose condition holds at `arg`,
+    /// falling through to the last (`otherwise`) case.
+    pub fn case_at(&self, arg: T) -> &Expr<T> {
+        for (cond, expr) in &self.cases[..self.cases.len() - 1] {
+            if cond.holds(arg) {
+                return expr;
+            }
+        }
+        &self.cases[self.cases.len() - 1].1
+    }
+}
+
 impl<T: CordicNumber + CordicMarker> Expr<T> {
     pub fn eval(&self, arg: T) -> T {
+        self.eval_in(arg, None)
+    }
+
+    fn eval_in(&self, arg: T, scope: Option<&Scope<T>>) -> T {
         use Expr::*;
         match self {
             Const(c) => *c,
-            Var(_s) => arg, // TODO: actually look up variable
-            Add(op1, op2) => op1.eval(arg) + op2.eval(arg),
-            Sub(op1, op2) => op1.eval(arg) - op2.eval(arg),
-            Mul(op1, op2) => op1.eval(arg) * op2.eval(arg),
-            Div(op1, op2) => op1.eval(arg) / op2.eval(arg),
-            Pow(op1, op2) => unimplemented!(),//cordic_powf(op1.eval(arg), op2.eval(arg)),
-            Log(op1, op2) => unimplemented!(),//cordic_log(op1.eval(arg), op2.eval(arg)),
-            Neg(op) => -op.eval(arg),
-            Sin(op) => cordic::sin(op.eval(arg)),
-            Cos(op) => cordic::cos(op.eval(arg)),
+            Var(s) => Scope::lookup(scope, s, arg),
+            Add(op1, op2) => op1.eval_in(arg, scope) + op2.eval_in(arg, scope),
+            Sub(op1, op2) => op1.eval_in(arg, scope) - op2.eval_in(arg, scope),
+            Mul(op1, op2) => op1.eval_in(arg, scope) * op2.eval_in(arg, scope),
+            Div(op1, op2) => op1.eval_in(arg, scope) / op2.eval_in(arg, scope),
+            Pow(op1, op2) => cordic_powf(op1.eval_in(arg, scope), op2.eval_in(arg, scope)),
+            Log(op1, op2) => cordic_log(op1.eval_in(arg, scope), op2.eval_in(arg, scope)),
+            Neg(op) => -op.eval_in(arg, scope),
+            Sin(op) => cordic::sin(op.eval_in(arg, scope)),
+            Cos(op) => cordic::cos(op.eval_in(arg, scope)),
+            Asin(op) => cordic::asin(op.eval_in(arg, scope)),
+            Acos(op) => cordic::acos(op.eval_in(arg, scope)),
+            Sqrt(op) => cordic::sqrt(op.eval_in(arg, scope)),
+            Bool(c) => {
+                if c.holds_in(arg, scope) {
+                    T::one()
+                } else {
+                    T::zero()
+                }
+            }
+            If(c, a, b) => {
+                if c.holds_in(arg, scope) {
+                    a.eval_in(arg, scope)
+                } else {
+                    b.eval_in(arg, scope)
+                }
+            }
+            Let(name, value, body) => {
+                let frame = Scope {
+                    name,
+                    value: value.eval_in(arg, scope),
+                    outer: scope,
+                };
+                body.eval_in(arg, Some(&frame))
+            }
         }
     }
 
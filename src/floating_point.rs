@@ -1,18 +1,104 @@
-use crate::ast::{CaseCondition, Equation, Expr, Openness, Piecewise};
+use crate::ast::{CaseCondition, Equation, Expr, Interval, Openness, Piecewise, Scope};
 use anyhow::{anyhow, Result};
+use lalrpop_util::ParseError;
+use std::collections::HashMap;
+
+/// Parse a numeric literal: a decimal with optional fraction, exponent and
+/// `_` separators (`1_000`, `.5`, `1.`, `6.02E23`), or a `0x` / `0b` integer.
+pub(crate) fn parse_literal(text: &str) -> Result<f64, &'static str> {
+    let (sign, body) = match text.strip_prefix('-') {
+        Some(body) => (-1.0, body),
+        None => (1.0, text),
+    };
+    let digits: String = body.chars().filter(|&c| c != '_').collect();
+    let radix = match digits.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0b") | Some("0B") => 2,
+        _ => 10,
+    };
+    let value = match radix {
+        10 => digits.parse::<f64>().map_err(|_| "invalid number literal")?,
+        _ => {
+            let n = u64::from_str_radix(&digits[2..], radix)
+                .map_err(|_| "invalid or too wide integer literal")?;
+            // above 2^53 not every integer is an f64, and rounding would
+            // silently change the bits the literal spells out
+            if n as f64 as u128 != u128::from(n) {
+                return Err("integer literal is not exactly representable as f64");
+            }
+            n as f64
+        }
+    };
+    Ok(sign * value)
+}
+
+/// Accept an expression only if its `let` bindings are well scoped and none
+/// of its conditions has a bare interval, since outside a piecewise condition
+/// there is no implicit argument for one to test.
+pub(crate) fn expression<L, T>(
+    e: Box<Expr<f64>>,
+) -> Result<Box<Expr<f64>>, ParseError<L, T, String>> {
+    if e.has_bare_interval() {
+        return Err(ParseError::User {
+            error: "an interval inside an expression must name its variable, as in \"x in [0, 1)\""
+                .into(),
+        });
+    }
+    e.check_scopes().map_err(|error| ParseError::User {
+        error: error.to_string(),
+    })?;
+    Ok(e)
+}
+
+/// `c` as a Rust `f64` expression: a suffixed literal, in exponent form when
+/// plain digits would be unwieldy, or a named constant for non-finite values.
+/// Negative values are parenthesized so that method calls bind to the sign.
+fn rust_literal(c: f64) -> String {
+    let literal = if c.is_nan() {
+        "f64::NAN".to_string()
+    } else if c.is_infinite() {
+        format!("{}f64::INFINITY", if c < 0.0 { "-" } else { "" })
+    } else if c != 0.0 && (c.abs() >= 1e16 || c.abs() < 1e-5) {
+        format!("{:e}_f64", c)
+    } else {
+        format!("{}_f64", c)
+    };
+    if literal.starts_with('-') {
+        format!("({})", literal)
+    } else {
+        literal
+    }
+}
+
+/// The value of a piecewise function where none of its cases applies.
+static NO_CASE: Expr<f64> = Expr::Const(f64::NAN);
 
 impl Piecewise<f64> {
     pub fn well_formed(&self) -> Result<()> {
-        if self.cases.len() == 0 {
+        if self.cases.is_empty() {
             return Err(anyhow!("Malformed piecewise function: missing any cases"));
         }
+        if let Some(regions) = self.regions() {
+            return self.well_formed_regions(&regions);
+        }
 
         let mut last_high = f64::NEG_INFINITY;
         let mut last_openness = Openness::Open;
+        // only bare intervals can be checked for overlap; any other
+        // condition is tested in order, so the first match wins
         for cond in &self.cases[..self.cases.len() - 1] {
+            if cond.0 == CaseCondition::Otherwise {
+                return Err(anyhow!("Malformed piecewise function: \"otherwise\" should only be the last piecewise case"));
+            }
             if let CaseCondition::Interval(i) = &cond.0 {
+                if i.is_inverted() {
+                    return Err(anyhow!(
+                        "Malformed piecewise function: inverted interval {}",
+                        i
+                    ));
+                }
                 match i.low_openness {
-                    Openness::Open => {
+                    Openness::Open | Openness::Unbounded => {
                         if i.low_val < last_high {
                             return Err(anyhow!(format!(
                                 "Malformed piecewise function: overlapping interval {}",
@@ -31,7 +117,7 @@ impl Piecewise<f64> {
                                 .to_string()));
                             }
                         }
-                        Openness::Open => {
+                        Openness::Open | Openness::Unbounded => {
                             if i.low_val < last_high {
                                 return Err(anyhow!(format!(
                                     "Malformed piecewise function: overlapping interval {}",
@@ -45,8 +131,6 @@ impl Piecewise<f64> {
 
                 last_high = i.high_val;
                 last_openness = i.high_openness;
-            } else {
-                return Err(anyhow!("Malformed piecewise function: \"otherwise\" should only be the last piecewise case"));
             }
         }
 
@@ -58,35 +142,309 @@ impl Piecewise<f64> {
     }
 
     pub fn eval(&self, arg: f64) -> f64 {
-        for case in &self.cases[..self.cases.len() - 1] {
-            if let CaseCondition::Interval(i) = &case.0 {
-                match (i.low_openness, i.high_openness) {
-                    (Openness::Closed, Openness::Closed) => {
-                        if arg >= i.low_val && arg <= i.high_val {
-                            return case.1.eval(arg);
-                        }
-                    }
-                    (Openness::Closed, Openness::Open) => {
-                        if arg >= i.low_val && arg < i.high_val {
-                            return case.1.eval(arg);
-                        }
-                    }
-                    (Openness::Open, Openness::Closed) => {
-                        if arg > i.low_val && arg <= i.high_val {
-                            return case.1.eval(arg);
-                        }
-                    }
-                    (Openness::Open, Openness::Open) => {
-                        if arg > i.low_val && arg < i.high_val {
-                            return case.1.eval(arg);
-                        }
-                    }
+        self.case_at(arg).eval(arg)
+    }
+
+    /// Evaluate with every variable looked up by name in `env`. Bare
+    /// interval conditions have no variable to test, so they fail here;
+    /// write them as `x in [a, b]` instead.
+    pub fn eval_with(&self, env: &HashMap<String, f64>) -> Result<f64> {
+        for (cond, expr) in &self.cases {
+            if cond.holds_with(env)? {
+                return expr.eval_with(env);
+            }
+        }
+        Err(anyhow!("Malformed piecewise function: no case applies"))
+    }
+
+    /// The expression of the first case whose condition holds at `arg`,
+    /// falling through to the last (`otherwise`) case. Boxes that leave out
+    /// `otherwise` give NaN outside all of them.
+    pub fn case_at(&self, arg: f64) -> &Expr<f64> {
+        self.cases
+            .iter()
+            .find(|(cond, _)| cond.holds(arg))
+            .map_or(&NO_CASE, |(_, expr)| expr)
+    }
+
+    /// An `if` / `else if` chain selecting the case for `arg`, the name bare
+    /// intervals are tested against, in the conditions and in the cases.
+    pub fn to_rust(&self, arg: &str) -> String {
+        let mut code = String::new();
+        for (cond, expr) in &self.cases {
+            if *cond == CaseCondition::Otherwise {
+                return code + &format!("{{ {} }}", expr.rust(Some(arg)));
+            }
+            code += &format!("if {} {{ {} }} else ", cond.to_rust(arg), expr.rust(Some(arg)));
+        }
+        code + &format!("{{ {} }}", NO_CASE.rust(Some(arg)))
+    }
+
+    /// Fails unless every case but `otherwise` is a bare interval, which the
+    /// analyses that split the input axis at case boundaries rely on.
+    pub(crate) fn require_intervals(&self, what: &str) -> Result<()> {
+        match self.cases.iter().find(|(cond, _)| {
+            !matches!(cond, CaseCondition::Interval(_) | CaseCondition::Otherwise)
+        }) {
+            Some((cond, _)) => Err(anyhow!(
+                "Cannot {} piecewise function: condition {} is not an interval",
+                what,
+                cond
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn intervals(&self) -> impl Iterator<Item = &Interval<f64>> {
+        self.cases.iter().filter_map(|(cond, _)| match cond {
+            CaseCondition::Interval(i) => Some(i),
+            _ => None,
+        })
+    }
+
+    /// The whole real line cut into the stretches governed by each case, left
+    /// to right, with the expression that applies on each. Gaps between
+    /// intervals and the unbounded tails are stretches of the `otherwise`
+    /// case. Fails unless the function is well formed and every case but
+    /// `otherwise` is a bare interval.
+    pub(crate) fn partition(&self) -> Result<Vec<(Interval<f64>, &Expr<f64>)>> {
+        self.well_formed()?;
+        self.require_intervals("partition")?;
+        let otherwise = &*self.cases[self.cases.len() - 1].1;
+        let gaps = self.gaps();
+
+        let mut regions = Vec::new();
+        let mut pending = gaps.iter().peekable();
+        for (cond, expr) in &self.cases[..self.cases.len() - 1] {
+            if let CaseCondition::Interval(i) = cond {
+                while let Some(g) = pending.next_if(|g| g.high_val <= i.low_val) {
+                    regions.push((g.clone(), otherwise));
                 }
+                regions.push((i.clone(), &**expr));
+            }
+        }
+        let left = match regions.first() {
+            Some((first, _)) if first.low_openness != Openness::Unbounded => {
+                Some(Interval::below(first.low_val, first.low_openness.flip()))
+            }
+            Some(_) => None,
+            None => Some(Interval::real_line()),
+        };
+        let right = match regions.last() {
+            Some((last, _)) if last.high_openness != Openness::Unbounded => {
+                Some(Interval::above(last.high_val, last.high_openness.flip()))
             }
+            _ => None,
+        };
+        if let Some(left) = left {
+            regions.insert(0, (left, otherwise));
         }
+        if let Some(right) = right {
+            regions.push((right, otherwise));
+        }
+        Ok(regions)
+    }
 
-        // fall through to default case
-        self.cases[self.cases.len() - 1].1.eval(arg)
+    /// The non-empty stretches between consecutive intervals, which is
+    /// where the `otherwise` case applies apart from the two unbounded tails.
+    /// Assumes the cases are well formed.
+    pub(crate) fn gaps(&self) -> Vec<Interval<f64>> {
+        let intervals: Vec<_> = self.intervals().collect();
+        intervals
+            .windows(2)
+            .filter(|w| {
+                w[0].high_val < w[1].low_val
+                    || (w[0].high_val == w[1].low_val
+                        && w[0].high_openness == Openness::Open
+                        && w[1].low_openness == Openness::Open)
+            })
+            .map(|w| {
+                Interval::new(
+                    w[0].high_val,
+                    w[1].low_val,
+                    w[0].high_openness.flip(),
+                    w[1].low_openness.flip(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl CaseCondition<f64> {
+    /// Whether the condition holds when every variable, and the implicit
+    /// argument of bare intervals, is bound to `arg`.
+    pub fn holds(&self, arg: f64) -> bool {
+        self.holds_in(arg, None)
+    }
+
+    fn holds_in(&self, arg: f64, scope: Option<&Scope<f64>>) -> bool {
+        use CaseCondition::*;
+        match self {
+            Otherwise => true,
+            Interval(i) => i.contains(arg),
+            In(v, i) => i.contains(Scope::lookup(scope, v, arg)),
+            Compare(op, a, b) => op.apply(a.eval_in(arg, scope), b.eval_in(arg, scope)),
+            And(a, b) => a.holds_in(arg, scope) && b.holds_in(arg, scope),
+            Or(a, b) => a.holds_in(arg, scope) || b.holds_in(arg, scope),
+            Not(c) => !c.holds_in(arg, scope),
+            NonZero(e) => e.eval_in(arg, scope) != 0.0,
+        }
+    }
+
+    pub fn holds_with(&self, env: &HashMap<String, f64>) -> Result<bool> {
+        use CaseCondition::*;
+        Ok(match self {
+            Otherwise => true,
+            Interval(i) => {
+                return Err(anyhow!(
+                    "Interval condition {} does not name the variable it tests",
+                    i
+                ))
+            }
+            In(v, i) => i.contains(
+                *env
+                    .get(v)
+                    .ok_or_else(|| anyhow!("Unbound variable \"{}\"", v))?,
+            ),
+            Compare(op, a, b) => op.apply(a.eval_with(env)?, b.eval_with(env)?),
+            And(a, b) => a.holds_with(env)? && b.holds_with(env)?,
+            Or(a, b) => a.holds_with(env)? || b.holds_with(env)?,
+            Not(c) => !c.holds_with(env)?,
+            NonZero(e) => e.eval_with(env)? != 0.0,
+        })
+    }
+
+    /// A Rust boolean expression for the condition, testing bare intervals
+    /// against the variable named `arg`.
+    pub fn to_rust(&self, arg: &str) -> String {
+        self.rust(Some(arg))
+    }
+
+    /// The code for `to_rust`, where `arg` is only `None` for conditions
+    /// without bare intervals.
+    fn rust(&self, arg: Option<&str>) -> String {
+        use CaseCondition::*;
+        match self {
+            Otherwise => "true".to_string(),
+            Interval(i) => i.to_rust(arg.expect("a bare interval has an argument to test")),
+            In(v, i) => i.to_rust(v),
+            Compare(op, a, b) => format!("({} {} {})", a.rust(arg), op, b.rust(arg)),
+            And(a, b) => format!("({} && {})", a.rust(arg), b.rust(arg)),
+            Or(a, b) => format!("({} || {})", a.rust(arg), b.rust(arg)),
+            Not(c) => format!("!{}", c.rust(arg)),
+            NonZero(e) => format!("({} != {})", e.rust(arg), rust_literal(0.0)),
+        }
+    }
+}
+
+/// Whether `e` is plain arithmetic, cheap enough to evaluate even when its
+/// value may go unused.
+fn is_cheap(e: &Expr<f64>) -> bool {
+    use Expr::*;
+    matches!(
+        e,
+        Const(_) | Var(_) | Add(..) | Sub(..) | Mul(..) | Neg(_) | Bool(_)
+    ) && e.children().into_iter().all(is_cheap)
+}
+
+/// The condition of a `Bool` or `If` as a Rust boolean. `and` and `or` skip
+/// short-circuiting, and so a branch, when their right side is cheap. Bare
+/// intervals test `arg`.
+fn rust_condition(cond: &CaseCondition<f64>, arg: Option<&str>) -> String {
+    use CaseCondition::*;
+    let cheap = |c: &CaseCondition<f64>| c.exprs().into_iter().all(is_cheap);
+    match cond {
+        And(a, b) if cheap(b) => {
+            format!("({} & {})", rust_condition(a, arg), rust_condition(b, arg))
+        }
+        Or(a, b) if cheap(b) => {
+            format!("({} | {})", rust_condition(a, arg), rust_condition(b, arg))
+        }
+        And(a, b) => format!("({} && {})", rust_condition(a, arg), rust_condition(b, arg)),
+        Or(a, b) => format!("({} || {})", rust_condition(a, arg), rust_condition(b, arg)),
+        Not(c) => format!("!{}", rust_condition(c, arg)),
+        c => c.rust(arg),
+    }
+}
+
+impl Interval<f64> {
+    /// The values in both intervals, if there are any.
+    pub fn intersection(&self, other: &Interval<f64>) -> Option<Interval<f64>> {
+        let (low_val, low_openness) = if self.low_val != other.low_val {
+            let i = if self.low_val > other.low_val { self } else { other };
+            (i.low_val, i.low_openness)
+        } else if self.low_openness == Openness::Open {
+            (self.low_val, Openness::Open)
+        } else {
+            (self.low_val, other.low_openness)
+        };
+        let (high_val, high_openness) = if self.high_val != other.high_val {
+            let i = if self.high_val < other.high_val { self } else { other };
+            (i.high_val, i.high_openness)
+        } else if self.high_openness == Openness::Open {
+            (self.high_val, Openness::Open)
+        } else {
+            (self.high_val, other.high_openness)
+        };
+
+        let nonempty = low_val < high_val
+            || (low_val == high_val
+                && low_openness == Openness::Closed
+                && high_openness == Openness::Closed);
+        nonempty.then(|| Interval::new(low_val, high_val, low_openness, high_openness))
+    }
+
+    /// Every value below `high_val`.
+    pub fn below(high_val: f64, high_openness: Openness) -> Self {
+        Interval::new(f64::NEG_INFINITY, high_val, Openness::Unbounded, high_openness)
+    }
+
+    /// Every value above `low_val`.
+    pub fn above(low_val: f64, low_openness: Openness) -> Self {
+        Interval::new(low_val, f64::INFINITY, low_openness, Openness::Unbounded)
+    }
+
+    pub fn real_line() -> Self {
+        Interval::new(
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            Openness::Unbounded,
+            Openness::Unbounded,
+        )
+    }
+
+    /// A Rust boolean expression testing `var`, which leaves out the
+    /// comparison at each unbounded end.
+    pub fn to_rust(&self, var: &str) -> String {
+        let low = match self.low_openness {
+            Openness::Open => Some(format!("{} > {}", var, rust_literal(self.low_val))),
+            Openness::Closed => Some(format!("{} >= {}", var, rust_literal(self.low_val))),
+            Openness::Unbounded => None,
+        };
+        let high = match self.high_openness {
+            Openness::Open => Some(format!("{} < {}", var, rust_literal(self.high_val))),
+            Openness::Closed => Some(format!("{} <= {}", var, rust_literal(self.high_val))),
+            Openness::Unbounded => None,
+        };
+        match (low, high) {
+            (Some(low), Some(high)) => format!("({} && {})", low, high),
+            (Some(bound), None) | (None, Some(bound)) => format!("({})", bound),
+            (None, None) => "true".to_string(),
+        }
+    }
+
+    pub fn contains(&self, x: f64) -> bool {
+        let above = match self.low_openness {
+            Openness::Open => x > self.low_val,
+            Openness::Closed => x >= self.low_val,
+            Openness::Unbounded => !x.is_nan(),
+        };
+        let below = match self.high_openness {
+            Openness::Open => x < self.high_val,
+            Openness::Closed => x <= self.high_val,
+            Openness::Unbounded => !x.is_nan(),
+        };
+        above && below
     }
 }
 
@@ -94,40 +452,151 @@ impl Equation<f64> {
     pub fn eval(&self, arg: f64) -> f64 {
         self.rhs().eval(arg)
     }
+
+    pub fn eval_with(&self, env: &HashMap<String, f64>) -> Result<f64> {
+        self.rhs().eval_with(env)
+    }
 }
 
 impl Expr<f64> {
     pub fn eval(&self, arg: f64) -> f64 {
+        self.eval_in(arg, None)
+    }
+
+    fn eval_in(&self, arg: f64, scope: Option<&Scope<f64>>) -> f64 {
         use Expr::*;
         match self {
             Const(c) => *c,
-            Var(_s) => arg, // TODO: actually look up variable
-            Add(op1, op2) => op1.eval(arg) + op2.eval(arg),
-            Sub(op1, op2) => op1.eval(arg) - op2.eval(arg),
-            Mul(op1, op2) => op1.eval(arg) * op2.eval(arg),
-            Div(op1, op2) => op1.eval(arg) / op2.eval(arg),
-            Pow(op1, op2) => op1.eval(arg).powf(op2.eval(arg)),
-            Log(op1, op2) => op1.eval(arg).log(op2.eval(arg)),
-            Neg(op) => -op.eval(arg),
-            Sin(op) => op.eval(arg).sin(),
-            Cos(op) => op.eval(arg).cos(),
+            Var(s) => Scope::lookup(scope, s, arg),
+            Add(op1, op2) => op1.eval_in(arg, scope) + op2.eval_in(arg, scope),
+            Sub(op1, op2) => op1.eval_in(arg, scope) - op2.eval_in(arg, scope),
+            Mul(op1, op2) => op1.eval_in(arg, scope) * op2.eval_in(arg, scope),
+            Div(op1, op2) => op1.eval_in(arg, scope) / op2.eval_in(arg, scope),
+            Pow(op1, op2) => op1.eval_in(arg, scope).powf(op2.eval_in(arg, scope)),
+            Log(op1, op2) => op1.eval_in(arg, scope).log(op2.eval_in(arg, scope)),
+            Neg(op) => -op.eval_in(arg, scope),
+            Sin(op) => op.eval_in(arg, scope).sin(),
+            Cos(op) => op.eval_in(arg, scope).cos(),
+            Asin(op) => op.eval_in(arg, scope).asin(),
+            Acos(op) => op.eval_in(arg, scope).acos(),
+            Sqrt(op) => op.eval_in(arg, scope).sqrt(),
+            Bool(c) => {
+                if c.holds_in(arg, scope) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            If(c, a, b) => {
+                if c.holds_in(arg, scope) {
+                    a.eval_in(arg, scope)
+                } else {
+                    b.eval_in(arg, scope)
+                }
+            }
+            // bound names are not variables, so they must not read `arg`
+            Let(name, value, body) => {
+                let frame = Scope {
+                    name,
+                    value: value.eval_in(arg, scope),
+                    outer: scope,
+                };
+                body.eval_in(arg, Some(&frame))
+            }
+        }
+    }
+
+    /// Evaluate with each variable looked up by name in `env`, failing on
+    /// any variable that is not bound.
+    pub fn eval_with(&self, env: &HashMap<String, f64>) -> Result<f64> {
+        use Expr::*;
+        Ok(match self {
+            Const(c) => *c,
+            Var(s) => *env
+                .get(s)
+                .ok_or_else(|| anyhow!("Unbound variable \"{}\"", s))?,
+            Add(op1, op2) => op1.eval_with(env)? + op2.eval_with(env)?,
+            Sub(op1, op2) => op1.eval_with(env)? - op2.eval_with(env)?,
+            Mul(op1, op2) => op1.eval_with(env)? * op2.eval_with(env)?,
+            Div(op1, op2) => op1.eval_with(env)? / op2.eval_with(env)?,
+            Pow(op1, op2) => op1.eval_with(env)?.powf(op2.eval_with(env)?),
+            Log(op1, op2) => op1.eval_with(env)?.log(op2.eval_with(env)?),
+            Neg(op) => -op.eval_with(env)?,
+            Sin(op) => op.eval_with(env)?.sin(),
+            Cos(op) => op.eval_with(env)?.cos(),
+            Asin(op) => op.eval_with(env)?.asin(),
+            Acos(op) => op.eval_with(env)?.acos(),
+            Sqrt(op) => op.eval_with(env)?.sqrt(),
+            Bool(c) => {
+                if c.holds_with(env)? {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            If(c, a, b) => {
+                if c.holds_with(env)? {
+                    a.eval_with(env)?
+                } else {
+                    b.eval_with(env)?
+                }
+            }
+            Let(name, value, body) => {
+                let mut env = env.clone();
+                env.insert(name.clone(), value.eval_with(&env)?);
+                body.eval_with(&env)?
+            }
+        })
+    }
+
+    /// Fails on a bare interval inside a condition, which only a hand-built
+    /// tree can hold, since there is no argument for it to test.
+    pub fn to_rust(&self) -> Result<String> {
+        if self.has_bare_interval() {
+            return Err(anyhow!(
+                "Cannot generate code for {}: a bare interval must be a piecewise condition",
+                self
+            ));
         }
+        Ok(self.rust(None))
     }
 
-    pub fn to_rust(&self) -> String {
+    /// The code for `to_rust`, with bare intervals testing `arg`, which is
+    /// only `None` for expressions without them.
+    pub(crate) fn rust(&self, arg: Option<&str>) -> String {
         use Expr::*;
         match self {
-            Const(c) => format!("{}_f64", c),
+            Const(c) => rust_literal(*c),
             Var(s) => s.to_owned(),
-            Add(op1, op2) => format!("({} + {})", op1.to_rust(), op2.to_rust()),
-            Sub(op1, op2) => format!("({} - {})", op1.to_rust(), op2.to_rust()),
-            Mul(op1, op2) => format!("({} * {})", op1.to_rust(), op2.to_rust()),
-            Div(op1, op2) => format!("({} / {})", op1.to_rust(), op2.to_rust()),
-            Pow(op1, op2) => format!("{}.powf({})", op1.to_rust(), op2.to_rust()),
-            Log(op1, op2) => format!("{}.log({})", op1.to_rust(), op2.to_rust()),
-            Neg(op) => format!("-{}", op.to_rust()),
-            Sin(op) => format!("{}.sin()", op.to_rust()),
-            Cos(op) => format!("{}.cos()", op.to_rust()),
+            Add(op1, op2) => format!("({} + {})", op1.rust(arg), op2.rust(arg)),
+            Sub(op1, op2) => format!("({} - {})", op1.rust(arg), op2.rust(arg)),
+            Mul(op1, op2) => format!("({} * {})", op1.rust(arg), op2.rust(arg)),
+            Div(op1, op2) => format!("({} / {})", op1.rust(arg), op2.rust(arg)),
+            Pow(op1, op2) => format!("{}.powf({})", op1.rust(arg), op2.rust(arg)),
+            Log(op1, op2) => format!("{}.log({})", op1.rust(arg), op2.rust(arg)),
+            Neg(op) => format!("(-{})", op.rust(arg)),
+            Sin(op) => format!("{}.sin()", op.rust(arg)),
+            Cos(op) => format!("{}.cos()", op.rust(arg)),
+            Asin(op) => format!("{}.asin()", op.rust(arg)),
+            Acos(op) => format!("{}.acos()", op.rust(arg)),
+            Sqrt(op) => format!("{}.sqrt()", op.rust(arg)),
+            Bool(c) => format!("f64::from(u8::from({}))", rust_condition(c, arg)),
+            // both sides are computed and one is picked without a branch
+            If(c, a, b) if is_cheap(a) && is_cheap(b) => format!(
+                "[{}, {}][usize::from({})]",
+                b.rust(arg),
+                a.rust(arg),
+                rust_condition(c, arg)
+            ),
+            If(c, a, b) => format!(
+                "(if {} {{ {} }} else {{ {} }})",
+                rust_condition(c, arg),
+                a.rust(arg),
+                b.rust(arg)
+            ),
+            Let(name, value, body) => {
+                format!("{{ let {} = {}; {} }}", name, value.rust(arg), body.rust(arg))
+            }
         }
     }
 }
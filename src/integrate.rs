@@ -0,0 +1,284 @@
+use crate::ast::{CaseCondition, Expr, Interval, Piecewise};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Quadrature {
+    AdaptiveSimpson,
+    /// Adaptive 7-point Gauss / 15-point Kronrod rule.
+    GaussKronrod,
+    Romberg,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QuadratureOptions {
+    /// Target absolute error of the result.
+    pub tolerance: f64,
+    /// Recursion depth for adaptive Simpson, number of subinterval splits for
+    /// Gauss-Kronrod, and number of extrapolation rows for Romberg.
+    pub max_depth: usize,
+}
+
+impl Default for QuadratureOptions {
+    fn default() -> Self {
+        QuadratureOptions {
+            tolerance: 1e-10,
+            max_depth: 50,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Integral {
+    pub value: f64,
+    pub error_estimate: f64,
+    pub evaluations: usize,
+    /// Whether `error_estimate` came within the requested tolerance before
+    /// `max_depth` was exhausted.
+    pub converged: bool,
+}
+
+impl Integral {
+    fn zero() -> Self {
+        Integral {
+            value: 0.0,
+            error_estimate: 0.0,
+            evaluations: 0,
+            converged: true,
+        }
+    }
+
+    fn merge(self, other: Integral) -> Integral {
+        Integral {
+            value: self.value + other.value,
+            error_estimate: self.error_estimate + other.error_estimate,
+            evaluations: self.evaluations + other.evaluations,
+            converged: self.converged && other.converged,
+        }
+    }
+}
+
+fn integrate(
+    f: &dyn Fn(f64) -> f64,
+    a: f64,
+    b: f64,
+    method: Quadrature,
+    opts: &QuadratureOptions,
+) -> Integral {
+    match method {
+        Quadrature::AdaptiveSimpson => adaptive_simpson(f, a, b, opts),
+        Quadrature::GaussKronrod => gauss_kronrod(f, a, b, opts),
+        Quadrature::Romberg => romberg(f, a, b, opts),
+    }
+}
+
+fn adaptive_simpson(f: &dyn Fn(f64) -> f64, a: f64, b: f64, opts: &QuadratureOptions) -> Integral {
+    let (fa, fm, fb) = (f(a), f((a + b) / 2.0), f(b));
+    let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+    let mut result = simpson_step(f, a, b, fa, fm, fb, whole, opts.tolerance, opts.max_depth);
+    result.evaluations += 3;
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn simpson_step(
+    f: &dyn Fn(f64) -> f64,
+    a: f64,
+    b: f64,
+    fa: f64,
+    fm: f64,
+    fb: f64,
+    whole: f64,
+    tolerance: f64,
+    depth: usize,
+) -> Integral {
+    let m = (a + b) / 2.0;
+    let (lm, rm) = ((a + m) / 2.0, (m + b) / 2.0);
+    let (flm, frm) = (f(lm), f(rm));
+    let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+    let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+    let delta = left + right - whole;
+
+    // Richardson extrapolation: the two-panel estimate is off by ~delta / 15.
+    // Splitting never repairs a NaN, so that gives up right away.
+    if delta.abs() <= 15.0 * tolerance || delta.is_nan() || depth == 0 {
+        return Integral {
+            value: left + right + delta / 15.0,
+            error_estimate: delta.abs() / 15.0,
+            evaluations: 2,
+            converged: delta.abs() <= 15.0 * tolerance,
+        };
+    }
+    let l = simpson_step(f, a, m, fa, flm, fm, left, tolerance / 2.0, depth - 1);
+    let r = simpson_step(f, m, b, fm, frm, fb, right, tolerance / 2.0, depth - 1);
+    let mut result = l.merge(r);
+    result.evaluations += 2;
+    result
+}
+
+#[allow(clippy::excessive_precision)]
+const KRONROD_NODES: [f64; 8] = [
+    0.991455371120812639206854697526329,
+    0.949107912342758524526189684047851,
+    0.864864423359769072789712788640926,
+    0.741531185599394439863864773280788,
+    0.586087235467691130294144845693013,
+    0.405845151377397166906606412076961,
+    0.207784955007898467600689403773245,
+    0.0,
+];
+
+#[allow(clippy::excessive_precision)]
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224963732008058970,
+    0.063092092629978553290700663189204,
+    0.104790010322250183839876322541518,
+    0.140653259715525918745189590510238,
+    0.169004726639267902826583426598550,
+    0.190350578064785409913256402421014,
+    0.204432940075298892414161999234649,
+    0.209482141084727828012999174891714,
+];
+
+// Gauss weights for the odd-indexed Kronrod nodes (the embedded 7-point rule)
+#[allow(clippy::excessive_precision)]
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.129484966168869693270611432679082,
+    0.279705391489276667901467771423780,
+    0.381830050505118944950369775488975,
+    0.417959183673469387755102040816327,
+];
+
+/// One 15-point Kronrod estimate of the integral over `[a, b]`, using the
+/// difference from the embedded Gauss rule as its error.
+fn kronrod_15(f: &dyn Fn(f64) -> f64, a: f64, b: f64) -> (f64, f64) {
+    let center = (a + b) / 2.0;
+    let half = (b - a) / 2.0;
+    let mut kronrod = 0.0;
+    let mut gauss = 0.0;
+    for (i, (&x, &w)) in KRONROD_NODES.iter().zip(&KRONROD_WEIGHTS).enumerate() {
+        let fx = if x == 0.0 {
+            f(center)
+        } else {
+            f(center - half * x) + f(center + half * x)
+        };
+        kronrod += w * fx;
+        if i % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[i / 2] * fx;
+        }
+    }
+    (kronrod * half, ((kronrod - gauss) * half).abs())
+}
+
+fn gauss_kronrod(f: &dyn Fn(f64) -> f64, a: f64, b: f64, opts: &QuadratureOptions) -> Integral {
+    let (value, error) = kronrod_15(f, a, b);
+    let mut pieces = vec![(a, b, value, error)];
+    let mut evaluations = 15;
+    for _ in 0..opts.max_depth {
+        let total_error: f64 = pieces.iter().map(|p| p.3).sum();
+        if total_error <= opts.tolerance {
+            break;
+        }
+        // split the piece contributing the most error
+        let worst = (0..pieces.len())
+            .max_by(|&i, &j| pieces[i].3.total_cmp(&pieces[j].3))
+            .unwrap();
+        let (a, b, _, _) = pieces.swap_remove(worst);
+        let m = (a + b) / 2.0;
+        let (lv, le) = kronrod_15(f, a, m);
+        let (rv, re) = kronrod_15(f, m, b);
+        pieces.push((a, m, lv, le));
+        pieces.push((m, b, rv, re));
+        evaluations += 30;
+    }
+    let error_estimate: f64 = pieces.iter().map(|p| p.3).sum();
+    Integral {
+        value: pieces.iter().map(|p| p.2).sum(),
+        error_estimate,
+        evaluations,
+        converged: error_estimate <= opts.tolerance,
+    }
+}
+
+fn romberg(f: &dyn Fn(f64) -> f64, a: f64, b: f64, opts: &QuadratureOptions) -> Integral {
+    // rows are capped so the number of trapezoid panels fits comfortably
+    let rows = opts.max_depth.clamp(2, 25);
+    let mut prev = vec![(b - a) / 2.0 * (f(a) + f(b))];
+    let mut evaluations = 2;
+    for n in 1..rows {
+        let panels = 1usize << (n - 1);
+        let h = (b - a) / (2 * panels) as f64;
+        let midpoints: f64 = (0..panels).map(|k| f(a + (2 * k + 1) as f64 * h)).sum();
+        evaluations += panels;
+
+        let mut row = vec![prev[0] / 2.0 + h * midpoints];
+        for m in 1..=n {
+            let scale = 4f64.powi(m as i32);
+            row.push(row[m - 1] + (row[m - 1] - prev[m - 1]) / (scale - 1.0));
+        }
+        let error = (row[n] - prev[n - 1]).abs();
+        if error <= opts.tolerance {
+            return Integral {
+                value: row[n],
+                error_estimate: error,
+                evaluations,
+                converged: true,
+            };
+        }
+        prev = row;
+    }
+    let n = prev.len() - 1;
+    Integral {
+        value: prev[n],
+        error_estimate: (prev[n] - prev[n - 1]).abs(),
+        evaluations,
+        converged: false,
+    }
+}
+
+impl Expr<f64> {
+    /// The definite integral of `self.eval(x)` from the interval's low bound
+    /// to its high bound. Openness has no effect on the result.
+    pub fn integrate(
+        &self,
+        interval: &Interval<f64>,
+        method: Quadrature,
+        opts: &QuadratureOptions,
+    ) -> Integral {
+        integrate(
+            &|x| self.eval(x),
+            interval.low_val,
+            interval.high_val,
+            method,
+            opts,
+        )
+    }
+}
+
+impl Piecewise<f64> {
+    /// The definite integral over `interval`, split at every case boundary
+    /// so that each piece is integrated using a single, smooth case. Only
+    /// interval bounds count as boundaries; where a comparison switches
+    /// cases, the quadrature has to cope with the jump itself.
+    pub fn integrate(
+        &self,
+        interval: &Interval<f64>,
+        method: Quadrature,
+        opts: &QuadratureOptions,
+    ) -> Integral {
+        let (a, b) = (interval.low_val, interval.high_val);
+        let mut points = vec![a, b];
+        for (cond, _) in &self.cases {
+            if let CaseCondition::Interval(i) | CaseCondition::In(_, i) = cond {
+                points.extend([i.low_val, i.high_val].iter().filter(|&&p| a < p && p < b));
+            }
+        }
+        points.sort_by(f64::total_cmp);
+        points.dedup();
+
+        points.windows(2).fold(Integral::zero(), |acc, w| {
+            // every point strictly inside a piece falls under the same case
+            let expr = self.case_at((w[0] + w[1]) / 2.0);
+            let piece = integrate(&|x| expr.eval(x), w[0], w[1], method, opts);
+            acc.merge(piece)
+        })
+    }
+}
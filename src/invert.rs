@@ -0,0 +1,193 @@
+use crate::ast::{CaseCondition, Equation, Expr, Interval, Openness, Piecewise};
+use crate::roots::RootOptions;
+use anyhow::{anyhow, Result};
+
+/// Number of sample points used to search a case for roots and to check it
+/// for monotonicity.
+const SAMPLES: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Monotonicity {
+    Increasing,
+    Decreasing,
+}
+
+/// The single variable a case expression is written in, if it has one.
+fn case_var(expr: &Expr<f64>) -> Option<String> {
+    let vars = expr.free_vars();
+    match vars.len() {
+        1 => vars.into_iter().next(),
+        _ => None,
+    }
+}
+
+/// Symbolically rearrange `out = expr` into `var = ...` solutions written in
+/// terms of `out`.
+fn solve_case(expr: &Expr<f64>, var: &str, out: &str) -> Result<Vec<Expr<f64>>> {
+    let eq = Equation::new(out.to_string(), Box::new(expr.clone()));
+    Ok(eq
+        .solve_for(var)?
+        .into_iter()
+        .map(|sol| sol.rhs().clone())
+        .collect())
+}
+
+impl Piecewise<f64> {
+    /// Every input `x` with `self.eval(x) == y`, in increasing order.
+    ///
+    /// Cases written in a single variable are solved symbolically where
+    /// possible; otherwise the case's interval is searched numerically. The
+    /// `otherwise` case is only searched numerically between intervals, so
+    /// roots on its unbounded tails are found only when it can be solved
+    /// symbolically, as are roots of interval cases with an unbounded end.
+    /// Cases under any other condition are only solved
+    /// symbolically. Cases that are constant contribute no inputs.
+    pub fn invert_eval(&self, y: f64) -> Vec<f64> {
+        let opts = RootOptions::default();
+        let mut xs = Vec::new();
+        for (cond, expr) in &self.cases {
+            // the case only produces `y` where it is the case selected
+            let in_domain = |x: f64| std::ptr::eq(self.case_at(x), &**expr);
+
+            let symbolic = case_var(expr)
+                .and_then(|var| solve_case(expr, &var, "__y").ok())
+                .map(|sols| sols.iter().map(|s| s.eval(y)).collect::<Vec<_>>());
+            if let Some(sols) = symbolic {
+                xs.extend(sols.into_iter().filter(|x| x.is_finite() && in_domain(*x)));
+                continue;
+            }
+
+            let shifted = Expr::Sub(expr.clone(), Box::new(Expr::Const(y)));
+            let domains = match cond {
+                CaseCondition::Interval(i) | CaseCondition::In(_, i) => vec![i.clone()],
+                CaseCondition::Otherwise => self.gaps(),
+                _ => vec![],
+            };
+            let bounded = |d: &Interval<f64>| {
+                d.low_openness != Openness::Unbounded && d.high_openness != Openness::Unbounded
+            };
+            for domain in domains.iter().filter(|d| bounded(d)) {
+                let roots = shifted.find_roots(domain, SAMPLES, &opts);
+                xs.extend(roots.iter().map(|r| r.x).filter(|x| in_domain(*x)));
+            }
+        }
+        xs.sort_by(f64::total_cmp);
+        xs.dedup_by(|a, b| (*a - *b).abs() <= opts.tolerance);
+        xs
+    }
+
+    /// The direction of every interval case, checked by sampling; fails if
+    /// any case is not strictly monotonic over its interval.
+    pub fn monotonicity(&self) -> Result<Vec<Monotonicity>> {
+        self.require_intervals("invert")?;
+        self.cases
+            .iter()
+            .filter_map(|(cond, expr)| match cond {
+                CaseCondition::Interval(i) => Some((i, expr)),
+                _ => None,
+            })
+            .map(|(i, expr)| {
+                if i.low_openness == Openness::Unbounded || i.high_openness == Openness::Unbounded {
+                    return Err(anyhow!(
+                        "Piecewise function not invertible: cannot sample {} on unbounded {}",
+                        expr,
+                        i
+                    ));
+                }
+                let width = i.high_val - i.low_val;
+                let ys: Vec<f64> = (0..=SAMPLES)
+                    .map(|k| expr.eval(i.low_val + width * k as f64 / SAMPLES as f64))
+                    .collect();
+                if ys.windows(2).all(|w| w[0] < w[1]) {
+                    Ok(Monotonicity::Increasing)
+                } else if ys.windows(2).all(|w| w[0] > w[1]) {
+                    Ok(Monotonicity::Decreasing)
+                } else {
+                    Err(anyhow!(
+                        "Piecewise function not invertible: {} is not monotonic on {}",
+                        expr,
+                        i
+                    ))
+                }
+            })
+            .collect()
+    }
+
+    /// The symbolic inverse, mapping each case's output range back to its
+    /// inputs.
+    ///
+    /// Every interval case must be strictly monotonic and solvable for its
+    /// variable, and their output ranges must not overlap. The `otherwise`
+    /// case is inverted symbolically and becomes the inverse's `otherwise`;
+    /// it is assumed, not checked, to be injective on its unbounded tails.
+    pub fn inverse(&self) -> Result<Piecewise<f64>> {
+        self.well_formed()?;
+        let directions = self.monotonicity()?;
+        let var = self
+            .cases
+            .iter()
+            .find_map(|(_, expr)| case_var(expr))
+            .ok_or_else(|| {
+                anyhow!("Piecewise function not invertible: no case depends on its input")
+            })?;
+
+        let invert = |expr: &Expr<f64>| -> Result<Box<Expr<f64>>> {
+            match case_var(expr) {
+                Some(v) if v == var => {}
+                _ => {
+                    return Err(anyhow!(
+                        "Piecewise function not invertible: {} is not a function of {} alone",
+                        expr,
+                        var
+                    ))
+                }
+            }
+            let mut sols = solve_case(expr, &var, &self.lhs)?;
+            match sols.len() {
+                1 => Ok(Box::new(sols.remove(0))),
+                _ => Err(anyhow!(
+                    "Piecewise function not invertible: {} has more than one inverse",
+                    expr
+                )),
+            }
+        };
+
+        let mut cases = Vec::new();
+        for ((cond, expr), dir) in self.cases.iter().zip(directions) {
+            if let CaseCondition::Interval(i) = cond {
+                let (lo, hi) = (expr.eval(i.low_val), expr.eval(i.high_val));
+                let range = match dir {
+                    Monotonicity::Increasing => {
+                        Interval::new(lo, hi, i.low_openness, i.high_openness)
+                    }
+                    Monotonicity::Decreasing => {
+                        Interval::new(hi, lo, i.high_openness, i.low_openness)
+                    }
+                };
+                cases.push((CaseCondition::Interval(range), invert(expr)?));
+            }
+        }
+        let last = match self.cases.last() {
+            Some((CaseCondition::Otherwise, expr)) => {
+                Some((CaseCondition::Otherwise, invert(expr)?))
+            }
+            _ => None,
+        };
+        cases.sort_by(|(a, _), (b, _)| match (a, b) {
+            (CaseCondition::Interval(a), CaseCondition::Interval(b)) => {
+                a.low_val.total_cmp(&b.low_val)
+            }
+            _ => std::cmp::Ordering::Equal,
+        });
+        cases.extend(last);
+
+        let inverse = Piecewise::new(var, cases);
+        inverse.well_formed().map_err(|e| {
+            anyhow!(
+                "Piecewise function not invertible: output ranges overlap ({})",
+                e
+            )
+        })?;
+        Ok(inverse)
+    }
+}
@@ -1,9 +1,26 @@
+pub mod algebra;
+pub mod analysis;
+pub mod approx;
 pub mod ast;
+pub mod calculus;
+pub mod compiled;
+pub mod dag;
 pub mod fixed_point;
 pub mod floating_point;
+pub mod integrate;
+pub mod invert;
+pub mod region;
+pub mod relaxed;
+pub mod roots;
+pub mod simplify;
+pub mod solve;
+pub mod subst;
+pub mod system;
 pub mod util;
+pub mod visit;
 
 pub use crate::ast::{Equation, Expr};
+pub use crate::system::EquationSystem;
 use ast::{CaseCondition, Interval, Piecewise};
 use equation::*;
 
@@ -11,7 +28,7 @@ use lalrpop_util::lalrpop_mod;
 use std::str::FromStr;
 
 lalrpop_mod!(
-    #[allow(unused)]
+    #[allow(unused, clippy::all)]
     equation
 );
 
@@ -166,6 +183,39 @@ impl<'de> Deserialize<'de> for Expr<f64> {
     }
 }
 
+impl Serialize for EquationSystem<f64> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Equations are separated by newlines or `;`. Blank entries are ignored.
+impl FromStr for EquationSystem<f64> {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let equations = s
+            .split(['\n', ';'])
+            .filter(|line| !line.trim().is_empty())
+            .map(Equation::from_str)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        EquationSystem::new(equations)
+    }
+}
+
+impl<'de> Deserialize<'de> for EquationSystem<f64> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        FromStr::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
 #[macro_export]
 macro_rules! eqn {
     ($e: expr) => {
@@ -176,9 +226,15 @@ macro_rules! eqn {
 #[cfg(test)]
 mod tests {
     use crate::{
+        analysis::CoverageOptions,
+        approx::{ApproxMethod, Interpolation, Segmentation},
         ast::{CaseCondition, Equation, Interval, Openness, Piecewise},
-        Expr,
+        integrate::{Quadrature, QuadratureOptions},
+        roots::{Derivative, RootMethod, RootOptions, RootStatus},
+        visit::{Fold, Visitor, VisitorMut},
+        EquationSystem, Expr,
     };
+    use std::collections::HashMap;
     use std::str::FromStr;
 
     #[test]
@@ -237,6 +293,22 @@ mod tests {
         assert_eq!(eq.eval(4.0), 2.0)
     }
 
+    #[test]
+    fn sqrt() {
+        let e = Expr::from_str("sqrt(x + 5)").unwrap();
+        assert_eq!(e.eval(4.0), 3.0);
+        assert_eq!(e.to_string(), "sqrt((x + 5))");
+        assert_eq!(e.to_rust().unwrap(), "(x + 5_f64).sqrt()");
+        assert_eq!(e.derivative("x").eval(4.0), 1.0 / 6.0);
+        assert_eq!(e.eval_with_derivative(4.0), (3.0, 1.0 / 6.0));
+        let fixed = e.to_fixed::<fixed::types::I16F16>();
+        assert_eq!(fixed.eval(fixed::types::I16F16::from_num(4)), 3.0);
+
+        let eq = Equation::from_str("y = sqrt(x - 1)").unwrap();
+        let sol = &eq.solve_for("x").unwrap()[0];
+        assert_eq!(sol.to_string(), "x = ((y^2) + 1)");
+    }
+
     #[test]
     fn paren1() {
         let s = "x = (2.0)";
@@ -300,6 +372,452 @@ mod tests {
         assert_eq!(eq.eval(2.0), -2.0)
     }
 
+    #[test]
+    fn unary_signs() {
+        let cases = [
+            ("-x^2", -9.0),
+            ("-(x^2)", -9.0),
+            ("(-x)^2", 9.0),
+            ("-2^2", -4.0),
+            ("2 * -x", -6.0),
+            ("2*-x", -6.0),
+            ("-x * 2", -6.0),
+            ("6 / -x", -2.0),
+            ("x ^ -1", 1.0 / 3.0),
+            ("2^-x^2", 2f64.powf(-9.0)),
+            ("x-1", 2.0),
+            ("x - -1", 4.0),
+            ("x+-1", 2.0),
+            ("--x", 3.0),
+            ("-+-x", 3.0),
+            ("+x", 3.0),
+            ("1 - x^2", -8.0),
+            ("-x - 1", -4.0),
+            ("sin(-x) + -1", (-3f64).sin() - 1.0),
+        ];
+        for (text, value) in cases {
+            let e = Expr::from_str(text).unwrap();
+            assert_eq!(e.eval(3.0), value, "{}", text);
+            let round_trip = Expr::from_str(&e.to_string()).unwrap();
+            assert_eq!(round_trip.eval(3.0), value, "{}", e);
+        }
+        assert!(Expr::from_str("x ^").is_err());
+        assert!(Expr::from_str("2 * ").is_err());
+
+        // method calls bind tighter than unary minus in the generated code too
+        let rust = [
+            ("(-x)^2", "(-x).powf(2_f64)"),
+            ("(-2)^2", "(-2_f64).powf(2_f64)"),
+            ("-x^2", "(-x.powf(2_f64))"),
+            ("cos(-x)", "(-x).cos()"),
+            ("2 * -x", "(2_f64 * (-x))"),
+        ];
+        for (text, code) in rust {
+            assert_eq!(
+                Expr::from_str(text).unwrap().to_rust().unwrap(),
+                code,
+                "{}",
+                text
+            );
+        }
+
+        let negative_base = Expr::Pow(Box::new(Expr::Const(-2.0)), Box::new(Expr::Const(2.0)));
+        assert_eq!(negative_base.to_string(), "((-2)^2)");
+        assert_eq!(
+            Expr::from_str(&negative_base.to_string())
+                .unwrap()
+                .eval(0.0),
+            4.0
+        );
+    }
+
+    #[test]
+    fn signed_interval_bounds() {
+        let p = Piecewise::from_str("y = { 1 if [-2, +2), -x if otherwise }").unwrap();
+        assert_eq!(p.eval(-2.0), 1.0);
+        assert_eq!(p.eval(2.0), -2.0);
+    }
+
+    #[test]
+    fn relaxed_syntax() {
+        let cases = [
+            ("3x^2 + 2x", "3 * x^2 + 2 * x"),
+            ("2(x + 1)", "2 * (x + 1)"),
+            ("(x + 1)(x - 1)", "(x + 1) * (x - 1)"),
+            ("x(x + 1)", "x * (x + 1)"),
+            ("sin x", "sin(x)"),
+            ("sin x^2", "sin(x^2)"),
+            ("sin x^-2 + 1", "sin(x^-2) + 1"),
+            ("2 sqrt x^2^y x", "2 * sqrt(x^2^y) * x"),
+            ("sqrt x", "sqrt(x)"),
+            ("2 sin x cos x", "2 * sin(x) * cos(x)"),
+            ("3sin(x)", "3 * sin(x)"),
+            ("2log(x, 2)", "2 * log(x, 2)"),
+            ("x**2", "x^2"),
+            ("2 × x ÷ 4 · 3", "2 * x / 4 * 3"),
+            ("x − 1", "x - 1"),
+            ("−x**−2", "-x^-2"),
+            ("2e", "2 * e"),
+            ("2e3x", "2e3 * x"),
+            ("0x1Fy", "0x1F * y"),
+            ("1/2x", "1 / 2 * x"),
+            ("2if(x < 1, 3x, x)", "2 * if(x < 1, 3 * x, x)"),
+        ];
+        for (relaxed, strict) in cases {
+            assert!(
+                Expr::from_str_relaxed(relaxed).unwrap() == Expr::from_str(strict).unwrap(),
+                "{}",
+                relaxed
+            );
+        }
+        // letters and digits still make up a single variable
+        assert!(Expr::from_str_relaxed("xy2").unwrap() == Expr::Var("xy2".into()));
+        assert!(Expr::from_str_relaxed("2 3").is_err());
+        assert!(Expr::from_str("3x").is_err());
+
+        let eq = Equation::from_str_relaxed("y = 3x^2 + 2(x − 1)").unwrap();
+        assert_eq!(eq.eval(2.0), 14.0);
+
+        let p = Piecewise::from_str_relaxed(
+            "y = { 2x if x in [0, 1), −x if (-inf, 0), x**2 if otherwise }",
+        )
+        .unwrap();
+        assert_eq!(p.eval(0.5), 1.0);
+        assert_eq!(p.eval(-3.0), 3.0);
+        assert_eq!(p.eval(3.0), 9.0);
+    }
+
+    #[test]
+    fn conditional_exprs() {
+        let cases = [
+            ("x < 2", 1.0, 1.0),
+            ("x < 2", 3.0, 0.0),
+            ("x == 2", 2.0, 1.0),
+            ("x != 2", 2.0, 0.0),
+            ("x >= 2", 2.0, 1.0),
+            ("(x > 0) + (x > 1)", 1.5, 2.0),
+            ("3 * (x in [0, 1)) + 1", 0.5, 4.0),
+            ("x > 0 and x < 2", 1.0, 1.0),
+            ("x > 0 and x < 2", 3.0, 0.0),
+            ("x < 0 or x > 2", 3.0, 1.0),
+            ("not x > 1", 3.0, 0.0),
+            ("not x", 0.0, 1.0),
+            ("x and 2", 3.0, 1.0),
+            ("x ? 1 : 2", 0.0, 2.0),
+            ("x ? 1 : 2", -3.0, 1.0),
+            ("(x - x) / 0 ? 1 : 2", 1.0, 1.0),
+            ("if(x < 0, -x, x)", -3.0, 3.0),
+            ("x < 0 ? -1 : x > 0 ? 1 : 0", 0.0, 0.0),
+            ("x < 0 ? -1 : x > 0 ? 1 : 0", 5.0, 1.0),
+            ("2 * if(x > 1 and x < 3, x, 0) + 1", 2.0, 5.0),
+        ];
+        for (text, x, value) in cases {
+            let e = Expr::from_str(text).unwrap();
+            assert_eq!(e.eval(x), value, "{}", text);
+            let round_trip = Expr::from_str(&e.to_string()).unwrap();
+            assert_eq!(round_trip.eval(x), value, "{}", e);
+        }
+
+        // intervals in expressions have no implicit argument to test
+        assert!(Expr::from_str("(0, 1) ? 1 : 2").is_err());
+        assert!(Expr::from_str("2 * ([0, 1) or x > 2)").is_err());
+        let p = Piecewise::from_str("y = {1 if ((0, 1) and x != 0.5), x if otherwise}").unwrap();
+        assert_eq!(p.eval(0.25), 1.0);
+        assert_eq!(p.eval(0.5), 0.5);
+        let p = Piecewise::from_str("y = {(x < 0 ? -1 : 1) if x, 0 if otherwise}").unwrap();
+        assert_eq!(p.eval(-2.0), -1.0);
+        assert_eq!(p.eval(0.0), 0.0);
+    }
+
+    #[test]
+    fn conditional_short_circuit() {
+        let e = Expr::from_str("if(x > 0, x, y)").unwrap();
+        let env = HashMap::from([("x".to_string(), 2.0)]);
+        assert_eq!(e.eval_with(&env).unwrap(), 2.0);
+        let env = HashMap::from([("x".to_string(), -2.0)]);
+        assert!(e.eval_with(&env).is_err());
+
+        let e = Expr::from_str("x > 0 or y > 0").unwrap();
+        let env = HashMap::from([("x".to_string(), 1.0)]);
+        assert_eq!(e.eval_with(&env).unwrap(), 1.0);
+        let e = Expr::from_str("x < 0 and y > 0").unwrap();
+        assert_eq!(e.eval_with(&env).unwrap(), 0.0);
+
+        // fixed-point division by zero panics, so the untaken side must not run
+        use fixed::types::I16F16;
+        let e = Expr::from_str("x == 0 ? 0 : 1 / x")
+            .unwrap()
+            .to_fixed::<I16F16>();
+        assert_eq!(e.eval(I16F16::from_num(0)), I16F16::from_num(0));
+        assert_eq!(e.eval(I16F16::from_num(4)), I16F16::from_num(0.25));
+        let e = Expr::from_str("x != 0 and 1 / x > 1")
+            .unwrap()
+            .to_fixed::<I16F16>();
+        assert_eq!(e.eval(I16F16::from_num(0)), I16F16::from_num(0));
+        assert_eq!(e.eval(I16F16::from_num(0.5)), I16F16::from_num(1));
+    }
+
+    #[test]
+    fn conditional_to_rust() {
+        let rust = |s: &str| Expr::from_str(s).unwrap().to_rust().unwrap();
+        assert_eq!(
+            rust("(x < 1) + 1"),
+            "(f64::from(u8::from((x < 1_f64))) + 1_f64)"
+        );
+        assert_eq!(
+            rust("x > 0 and x < 1"),
+            "f64::from(u8::from(((x > 0_f64) & (x < 1_f64))))"
+        );
+        assert_eq!(
+            rust("x > 0 or sin(x) > 0"),
+            "f64::from(u8::from(((x > 0_f64) || (x.sin() > 0_f64))))"
+        );
+        assert_eq!(
+            rust("if(x < 0, -x, x)"),
+            "[x, (-x)][usize::from((x < 0_f64))]"
+        );
+        assert_eq!(
+            rust("x in [0, 1) ? 2 * x : 0"),
+            "[0_f64, (2_f64 * x)][usize::from((x >= 0_f64 && x < 1_f64))]"
+        );
+        assert_eq!(
+            rust("x ? sin(x) : 1"),
+            "(if (x != 0_f64) { x.sin() } else { 1_f64 })"
+        );
+
+        // a bare interval, which only a hand-built tree can hold, has no
+        // argument to test outside a piecewise function
+        let bare = Expr::If(
+            Box::new(CaseCondition::from_str("[0, 1)").unwrap()),
+            Box::new(Expr::Const(1.0)),
+            Box::new(Expr::Const(0.0)),
+        );
+        assert!(bare.to_rust().is_err());
+        let p = Piecewise {
+            lhs: "y".into(),
+            cases: vec![(CaseCondition::Otherwise, Box::new(bare))],
+        };
+        assert_eq!(
+            p.to_rust("t"),
+            "{ [0_f64, 1_f64][usize::from((t >= 0_f64 && t < 1_f64))] }"
+        );
+    }
+
+    #[test]
+    fn conditional_calculus() {
+        let e = Expr::from_str("if(x < 0, -x, x^2) + (x > 1)").unwrap();
+        let d = e.derivative("x");
+        assert_eq!(d.eval(-1.0), -1.0);
+        assert_eq!(d.eval(3.0), 6.0);
+        assert_eq!(e.eval_with_derivative(3.0), (10.0, 6.0));
+
+        let simplified = Expr::from_str("if(1 < 2, x, y) + (1 > 2)")
+            .unwrap()
+            .simplify();
+        assert_eq!(simplified.to_string(), "x");
+        let kept = Expr::from_str("x in [0, 1) ? 1 : 1 + 1")
+            .unwrap()
+            .simplify();
+        assert_eq!(kept.to_string(), "(x in [0, 1) ? 1 : 2)");
+        assert_eq!(
+            Expr::from_str("y in [0, 1)").unwrap().free_vars(),
+            ["y".to_string()].into()
+        );
+
+        let e = Expr::from_str("x in [0, 1) ? x : 0").unwrap();
+        let shifted = e.substitute("x", &Expr::from_str("t - 1").unwrap());
+        assert_eq!(shifted.eval(1.5), 0.5);
+        assert_eq!(shifted.eval(3.0), 0.0);
+    }
+
+    #[test]
+    fn let_bindings() {
+        let e = Expr::from_str("let r = sqrt(x^2 + y^2) in r * cos(r)").unwrap();
+        let env = HashMap::from([("x".to_string(), 3.0), ("y".to_string(), 4.0)]);
+        assert_eq!(e.eval_with(&env).unwrap(), 5.0 * 5f64.cos());
+        assert_eq!(e.free_vars(), ["x".to_string(), "y".to_string()].into());
+
+        let cases = [
+            ("let r = x + 1 in r * r", 9.0),
+            ("r * r where r = x + 1", 9.0),
+            ("a + b where a = b * 2 where b = x + 1", 9.0),
+            ("let a = x + 1 in let b = a * 2 in a + b", 9.0),
+            ("2 * (let r = x in r + 1) + 1", 7.0),
+            ("let r = x - 1 in r > 0 ? r : -r", 1.0),
+            ("2 * (r where r = x - 5)", -6.0),
+            ("let r = x in let s = r * 3 in s + r", 8.0),
+            ("let r = x - 3 in (r in [-1, 0] ? 10 : 20) + r", 9.0),
+        ];
+        for (text, value) in cases {
+            let e = Expr::from_str(text).unwrap();
+            assert_eq!(e.eval(2.0), value, "{}", text);
+            let round_trip = Expr::from_str(&e.to_string()).unwrap();
+            assert_eq!(round_trip.eval(2.0), value, "{}", e);
+            let fixed = e.to_fixed::<fixed::types::I16F16>();
+            assert_eq!(
+                fixed.eval(fixed::types::I16F16::from_num(2)),
+                value,
+                "{}",
+                text
+            );
+        }
+        assert_eq!(
+            Expr::from_str("let r = x + 1 in r * r")
+                .unwrap()
+                .to_rust()
+                .unwrap(),
+            "{ let r = (x + 1_f64); (r * r) }"
+        );
+        assert!(
+            Expr::from_str_relaxed("let r = 2x in 3r").unwrap()
+                == Expr::from_str("let r = 2 * x in 3 * r").unwrap()
+        );
+    }
+
+    #[test]
+    fn let_scoping_errors() {
+        let err = |s: &str| Expr::from_str(s).err().unwrap().to_string();
+        assert!(err("let r = 1 in let r = 2 in r").contains("already bound"));
+        assert!(err("x + (let x = 2 in x)").contains("shadows the input"));
+        assert!(err("let r = r + 1 in r").contains("unbound in its own definition"));
+        assert!(err("r where r = r").contains("unbound in its own definition"));
+        assert!(Equation::from_str("y = let y = 2 in y").is_err());
+        assert!(Piecewise::from_str("y = {let y = 2 in y if otherwise}").is_err());
+        // sibling bindings may share a name
+        assert!(Expr::from_str("(let r = x in r) + (let r = 2 * x in r)").is_ok());
+    }
+
+    #[test]
+    fn let_calculus() {
+        let e = Expr::from_str("let r = x^2 in r * r").unwrap();
+        assert_eq!(e.derivative("x").eval(2.0), 32.0);
+        assert_eq!(e.eval_with_derivative(2.0), (16.0, 32.0));
+        assert_eq!(e.antiderivative("x").unwrap().eval(1.0), 0.2);
+        assert_eq!(
+            Expr::from_str("let r = 2 in r * x")
+                .unwrap()
+                .simplify()
+                .to_string(),
+            "(2 * x)"
+        );
+        assert_eq!(
+            Expr::from_str("let r = x in 3")
+                .unwrap()
+                .simplify()
+                .to_string(),
+            "3"
+        );
+
+        // substitution leaves the bound name alone
+        let e = Expr::from_str("let r = x + 1 in r * y").unwrap();
+        let swapped = e.substitute("y", &Expr::Var("x".into()));
+        assert_eq!(swapped.to_string(), "(let r = (x + 1) in (r * x))");
+        let shadowed = Expr::Let(
+            "r".into(),
+            Box::new(Expr::Var("r".into())),
+            Box::new(Expr::Var("r".into())),
+        );
+        assert_eq!(
+            shadowed.substitute("r", &Expr::Const(1.0)).to_string(),
+            "(let r = 1 in r)"
+        );
+
+        // a replacement reading the bound name renames the binding
+        let captured = e.substitute("y", &Expr::Var("r".into()));
+        assert_eq!(captured.to_string(), "(let r1 = (x + 1) in (r1 * r))");
+        let env = HashMap::from([("x".to_string(), 2.0), ("r".to_string(), 5.0)]);
+        assert_eq!(captured.eval_with(&env).unwrap(), 15.0);
+        let e = Expr::from_str("let r = x in r * y + r1").unwrap();
+        assert_eq!(
+            e.substitute("y", &Expr::Var("r".into())).to_string(),
+            "(let r2 = x in ((r2 * r) + r1))"
+        );
+    }
+
+    #[test]
+    fn common_subexprs() {
+        let e = Expr::from_str("sin(x) * sin(x)").unwrap();
+        let dag = e.to_dag().unwrap();
+        assert_eq!(dag.nodes().len(), 3);
+        assert_eq!(dag.shared(), vec![1]);
+        assert_eq!(dag.to_expr().to_string(), "(let t1 = sin(x) in (t1 * t1))");
+        assert_eq!(dag.eval(0.5), e.eval(0.5));
+        assert_eq!(dag.to_rust(), "{ let t1 = x.sin(); (t1 * t1) }");
+
+        // let bindings are shared with equal subtrees
+        let e = Expr::from_str("(x + 1) * r where r = x + 1").unwrap();
+        assert_eq!(
+            e.eliminate_common_subexprs().unwrap().to_string(),
+            "(let t2 = (x + 1) in (t2 * t2))"
+        );
+
+        // a temporary never clashes with a variable
+        let e = Expr::from_str("(t1 + 1) * (t1 + 1)").unwrap();
+        let cse = e.eliminate_common_subexprs().unwrap();
+        assert_eq!(cse.to_string(), "(let tt2 = (t1 + 1) in (tt2 * tt2))");
+        let env = HashMap::from([("t1".to_string(), 2.0)]);
+        assert_eq!(e.to_dag().unwrap().eval_with(&env).unwrap(), 9.0);
+        assert!(e.to_dag().unwrap().eval_with(&HashMap::new()).is_err());
+
+        let otherwise = Expr::Bool(Box::new(CaseCondition::<f64>::Otherwise));
+        assert!(otherwise.to_dag().is_err());
+    }
+
+    #[test]
+    fn common_subexprs_in_conditionals() {
+        use fixed::types::I16F16;
+
+        // temporaries used by one branch are computed in that branch
+        let e = Expr::from_str("x == 0 ? 0 : (1 / x) * (1 / x)").unwrap();
+        assert_eq!(
+            e.eliminate_common_subexprs().unwrap().to_string(),
+            "(x == 0 ? 0 : (let t4 = (1 / x) in (t4 * t4)))"
+        );
+        let dag = e.to_fixed::<I16F16>().to_dag().unwrap();
+        assert_eq!(dag.eval(I16F16::from_num(0)), I16F16::from_num(0));
+        assert_eq!(dag.eval(I16F16::from_num(2)), I16F16::from_num(0.25));
+        let env = HashMap::from([("x".to_string(), I16F16::from_num(4))]);
+        assert_eq!(dag.eval_with(&env).unwrap(), I16F16::from_num(0.0625));
+        assert!(dag.eval_with(&HashMap::new()).is_err());
+
+        // powers and logarithms evaluate as they do in the tree
+        let e = Expr::from_str("x^2 + x^2").unwrap().to_fixed::<I16F16>();
+        assert_eq!(
+            e.to_dag().unwrap().eval(I16F16::from_num(2)),
+            I16F16::from_num(8)
+        );
+        let e = Expr::from_str("x < 0 ? log(-x, 2) : sqrt(x)")
+            .unwrap()
+            .to_fixed::<I16F16>();
+        let dag = e.to_dag().unwrap();
+        for x in [4, -4] {
+            assert_eq!(dag.eval(I16F16::from_num(x)), e.eval(I16F16::from_num(x)));
+        }
+        assert_eq!(dag.eval(I16F16::from_num(4)), I16F16::from_num(2));
+
+        // ...and those the condition also uses before branching
+        let e = Expr::from_str("x + 1 > 2 ? x + 1 : 0").unwrap();
+        let cse = e.eliminate_common_subexprs().unwrap();
+        assert_eq!(cse.to_string(), "(let t2 = (x + 1) in (t2 > 2 ? t2 : 0))");
+        let dag = e.to_dag().unwrap();
+        for x in [0.0, 1.0, 3.0] {
+            assert_eq!(dag.eval(x), e.eval(x));
+            assert_eq!(cse.eval(x), e.eval(x));
+        }
+
+        // shared conditions
+        let e = Expr::from_str("(x > 1) + (x > 1 and y in [0, 1])").unwrap();
+        let dag = e.to_dag().unwrap();
+        let env = HashMap::from([("x".to_string(), 2.0), ("y".to_string(), 0.5)]);
+        assert_eq!(dag.eval_with(&env).unwrap(), 2.0);
+        assert_eq!(
+            dag.to_expr().to_string(),
+            "(let t2 = (x > 1) in (t2 + (t2 and y in [0, 1])))"
+        );
+        let reparsed = Expr::from_str(&dag.to_expr().to_string()).unwrap();
+        assert_eq!(reparsed.eval_with(&env).unwrap(), 2.0);
+    }
+
     #[test]
     fn to_string() {
         let s = "x = (y + 1) * (y + 2)";
@@ -313,7 +831,7 @@ mod tests {
     fn to_rust_string_1() {
         let s = "(y + 1) * (y + 2)";
         let eq = Expr::from_str(s).unwrap();
-        let s2 = eq.to_rust();
+        let s2 = eq.to_rust().unwrap();
         assert_eq!(s2, "((y + 1_f64) * (y + 2_f64))")
     }
 
@@ -321,21 +839,21 @@ mod tests {
     fn to_rust_string_2() {
         let s = "sin(cos(x))";
         let eq = Expr::from_str(s).unwrap();
-        let s2 = eq.to_rust();
+        let s2 = eq.to_rust().unwrap();
         assert_eq!(s2, "x.cos().sin()")
     }
 
     #[test]
     fn eqn_macro_1() {
         let s = eqn!(sin(cos(x)));
-        let eq = Expr::from_str(s).unwrap();
+        Expr::from_str(s).unwrap();
         assert_eq!(s, "sin(cos(x))")
     }
 
     #[test]
     fn eqn_macro_2() {
         let s = eqn!(x ^ 2 ^ 3);
-        let eq = Expr::from_str(s).unwrap();
+        Expr::from_str(s).unwrap();
         assert_eq!(s, "x ^ 2 ^ 3")
     }
 
@@ -374,4 +892,1065 @@ mod tests {
         assert_eq!(peq.eval(-10.0), -10.0);
         assert_eq!(peq.eval(20.0), 20.0);
     }
+
+    #[test]
+    fn visitor_counts_consts() {
+        struct ConstCounter(usize);
+        impl Visitor<f64> for ConstCounter {
+            fn visit_const(&mut self, _c: &f64) {
+                self.0 += 1;
+            }
+        }
+        let e = Expr::from_str("3 * x^2 + log(x, 2.0) - 1").unwrap();
+        let mut counter = ConstCounter(0);
+        counter.visit_expr(&e);
+        assert_eq!(counter.0, 4);
+    }
+
+    #[test]
+    fn visitor_mut_renames_vars() {
+        struct Rename;
+        impl VisitorMut<f64> for Rename {
+            fn visit_var_mut(&mut self, name: &mut String) {
+                if name == "x" {
+                    *name = "t".to_string();
+                }
+            }
+        }
+        let mut e = Expr::from_str("sin(x) + y * x").unwrap();
+        Rename.visit_expr_mut(&mut e);
+        assert_eq!(e.to_string(), "(sin(t) + (y * t))");
+
+        // the variables of `in` tests and let binders are variables too
+        let mut e = Expr::from_str("let x = y in x in [0, 1) ? x : 2").unwrap();
+        Rename.visit_expr_mut(&mut e);
+        assert_eq!(e.to_string(), "(let t = y in (t in [0, 1) ? t : 2))");
+    }
+
+    #[test]
+    fn visitor_and_fold_see_every_var() {
+        struct Names(Vec<String>);
+        impl Visitor<f64> for Names {
+            fn visit_var(&mut self, name: &str) {
+                self.0.push(name.to_string());
+            }
+        }
+        let e = Expr::from_str("let r = x in (r in [0, 1) and y > r ? r : 0)").unwrap();
+        let mut names = Names(vec![]);
+        names.visit_expr(&e);
+        assert_eq!(names.0, ["r", "x", "r", "y", "r", "r"]);
+
+        struct Rename;
+        impl Fold<f64> for Rename {
+            fn fold_var(&mut self, name: String) -> String {
+                name.to_uppercase()
+            }
+        }
+        let renamed = Rename.fold_expr(e);
+        assert_eq!(
+            renamed.to_string(),
+            "(let R = X in ((R in [0, 1) and Y > R) ? R : 0))"
+        );
+        let env = HashMap::from([("X".to_string(), 0.5), ("Y".to_string(), 1.0)]);
+        assert_eq!(renamed.eval_with(&env).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn fold_doubles_consts() {
+        struct Double;
+        impl Fold<f64> for Double {
+            fn fold_const(&mut self, c: f64) -> f64 {
+                c * 2.0
+            }
+        }
+        let e = Expr::from_str("x + 1.5").unwrap();
+        assert_eq!(Double.fold_expr(e).eval(1.0), 4.0);
+    }
+
+    #[test]
+    fn subexpr_iterators() {
+        let e = Expr::from_str("(x + 1) * cos(y)").unwrap();
+        assert_eq!(e.subexprs().count(), 6);
+        assert_eq!(e.vars().collect::<Vec<_>>(), vec!["x", "y"]);
+    }
+
+    #[test]
+    fn substitute_var() {
+        let e = Expr::from_str("x * x + y").unwrap();
+        let r = Expr::from_str("t + 1").unwrap();
+        assert_eq!(e.substitute("x", &r).to_string(), "(((t + 1) * (t + 1)) + y)");
+    }
+
+    #[test]
+    fn substitute_all_is_simultaneous() {
+        let e = Expr::from_str("x - y").unwrap();
+        let bindings = HashMap::from([
+            ("x".to_string(), Expr::Var("y".to_string())),
+            ("y".to_string(), Expr::Var("x".to_string())),
+        ]);
+        assert_eq!(e.substitute_all(&bindings).to_string(), "(y - x)");
+    }
+
+    #[test]
+    fn compose_equations() {
+        let outer = Equation::from_str("y = 2 * x").unwrap();
+        let inner = Equation::from_str("x = t + 1").unwrap();
+        let composed = outer.compose(&inner);
+        assert_eq!(composed.to_string(), "y = (2 * (t + 1))");
+        assert_eq!(composed.eval(3.0), 8.0);
+    }
+
+    #[test]
+    fn free_vars() {
+        let e = Expr::from_str("z * sin(x) + x / y").unwrap();
+        let vars: Vec<_> = e.free_vars().into_iter().collect();
+        assert_eq!(vars, vec!["x", "y", "z"]);
+    }
+
+    #[test]
+    fn equation_inputs() {
+        let eq = Equation::from_str("v = a * t + v0").unwrap();
+        let inputs: Vec<_> = eq.inputs().into_iter().collect();
+        assert_eq!(inputs, vec!["a", "t", "v0"]);
+        assert!(eq.well_formed().is_ok());
+    }
+
+    #[test]
+    fn recursive_equation_not_well_formed() {
+        let eq = Equation::from_str("x = log(x, 2.0)").unwrap();
+        assert!(eq.well_formed().is_err());
+    }
+
+    #[test]
+    fn system_orders_and_evals() {
+        let s = "area = w * h; volume = area * d\nw = 2 * side";
+        let sys = EquationSystem::from_str(s).unwrap();
+        assert_eq!(sys.outputs(), vec!["w", "area", "volume"]);
+        let inputs: Vec<_> = sys.inputs().into_iter().collect();
+        assert_eq!(inputs, vec!["d", "h", "side"]);
+        let env = HashMap::from([
+            ("side".to_string(), 1.5),
+            ("h".to_string(), 2.0),
+            ("d".to_string(), 4.0),
+        ]);
+        let out = sys.eval(&env).unwrap();
+        assert_eq!(out["volume"], 24.0);
+    }
+
+    #[test]
+    fn system_missing_input() {
+        let sys = EquationSystem::from_str("y = x + 1").unwrap();
+        assert!(sys.eval(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn system_cycle() {
+        let err = EquationSystem::from_str("a = b + 1; b = c * 2; c = a")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Malformed equation system: cyclic dependency a -> b -> c -> a"
+        );
+        assert!(EquationSystem::from_str("x = log(x, 2.0)").is_err());
+        assert!(EquationSystem::from_str("x = 1; x = 2").is_err());
+    }
+
+    #[test]
+    fn solve_linear() {
+        let eq = Equation::from_str("y = 3 * x + 1").unwrap();
+        let sols = eq.solve_for("x").unwrap();
+        assert_eq!(sols.len(), 1);
+        assert_eq!(sols[0].to_string(), "x = ((y - 1) / 3)");
+        assert_eq!(sols[0].eval(7.0), 2.0);
+    }
+
+    #[test]
+    fn solve_through_invertible_ops() {
+        let eq = Equation::from_str("y = -log(x, 2.0) ^ 3").unwrap();
+        let sol = &eq.solve_for("x").unwrap()[0];
+        let env = HashMap::from([("y".to_string(), -27.0)]);
+        assert!((sol.eval_with(&env).unwrap() - 8.0).abs() < 1e-9);
+
+        let eq = Equation::from_str("y = sin(2 * x)").unwrap();
+        let sol = &eq.solve_for("x").unwrap()[0];
+        assert_eq!(sol.to_string(), "x = (asin(y) / 2)");
+    }
+
+    #[test]
+    fn solve_quadratic() {
+        let eq = Equation::from_str("y = x^2 - 3 * x").unwrap();
+        let sols = eq.solve_for("x").unwrap();
+        assert_eq!(sols.len(), 2);
+        let env = HashMap::from([("y".to_string(), 4.0)]);
+        let mut roots: Vec<f64> = sols.iter().map(|s| s.eval_with(&env).unwrap()).collect();
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(roots, vec![-1.0, 4.0]);
+    }
+
+    #[test]
+    fn solve_even_power() {
+        let eq = Equation::from_str("y = x^2").unwrap();
+        let sols = eq.solve_for("x").unwrap();
+        assert_eq!(sols.len(), 2);
+        let env = HashMap::from([("y".to_string(), 9.0)]);
+        let roots: Vec<f64> = sols.iter().map(|s| s.eval_with(&env).unwrap()).collect();
+        assert_eq!(roots, vec![3.0, -3.0]);
+
+        let eq = Equation::from_str("y = 2 * (x - 1)^4").unwrap();
+        let env = HashMap::from([("y".to_string(), 32.0)]);
+        let roots: Vec<f64> = eq
+            .solve_for("x")
+            .unwrap()
+            .iter()
+            .map(|s| s.eval_with(&env).unwrap())
+            .collect();
+        assert_eq!(roots, vec![3.0, -1.0]);
+    }
+
+    #[test]
+    fn solve_non_invertible() {
+        let eq = Equation::from_str("y = sin(x) + x").unwrap();
+        let err = eq.solve_for("x").err().unwrap();
+        assert!(err.to_string().contains("non-invertibly"));
+        assert!(eq.solve_for("z").is_err());
+    }
+
+    #[test]
+    fn derivative() {
+        let e = Expr::from_str("3 * x^2 + sin(x)").unwrap();
+        assert_eq!(e.derivative("x").to_string(), "((3 * (2 * x)) + cos(x))");
+        assert_eq!(e.derivative("y").to_string(), "0");
+        let (v, dv) = e.eval_with_derivative(0.0);
+        assert_eq!((v, dv), (0.0, 1.0));
+    }
+
+    #[test]
+    fn log_derivative() {
+        let ln2 = 2f64.ln();
+        let e = Expr::from_str("log(x, 2)").unwrap();
+        let d = e.derivative("x");
+        assert!((d.eval(4.0) - 1.0 / (4.0 * ln2)).abs() < 1e-12);
+        assert!((e.eval_with_derivative(4.0).1 - d.eval(4.0)).abs() < 1e-12);
+
+        // a variable base goes through the quotient rule
+        let e = Expr::from_str("log(2, x)").unwrap();
+        let x = 4f64;
+        let expected = -ln2 / (x * x.ln().powi(2));
+        assert!((e.derivative("x").eval(x) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn root_methods_agree() {
+        let e = Expr::from_str("x^3 - 2 * x - 5").unwrap();
+        let interval = Interval::new(2.0, 3.0, Openness::Closed, Openness::Closed);
+        let opts = RootOptions::default();
+        for method in [
+            RootMethod::Bisection,
+            RootMethod::Brent,
+            RootMethod::Newton(Derivative::Symbolic),
+            RootMethod::Newton(Derivative::Automatic),
+        ] {
+            let r = e.find_root(&interval, method, &opts);
+            assert!(r.converged(), "{:?}", method);
+            assert!((r.x - 2.0945514815423265).abs() < 1e-9, "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn root_status_reported() {
+        let e = Expr::from_str("x^2 + 1").unwrap();
+        let interval = Interval::new(-1.0, 1.0, Openness::Closed, Openness::Closed);
+        let opts = RootOptions::default();
+        let r = e.find_root(&interval, RootMethod::Brent, &opts);
+        assert_eq!(r.status, RootStatus::NoSignChange);
+        let r = e.find_root(&interval, RootMethod::Newton(Derivative::Automatic), &opts);
+        assert_eq!(r.status, RootStatus::Diverged);
+    }
+
+    #[test]
+    fn find_all_roots() {
+        let e = Expr::from_str("sin(x)").unwrap();
+        let interval = Interval::new(-1.0, 10.0, Openness::Closed, Openness::Closed);
+        let roots = e.find_roots(&interval, 50, &RootOptions::default());
+        let xs: Vec<f64> = roots.iter().map(|r| r.x).collect();
+        assert_eq!(xs.len(), 4);
+        for (x, k) in xs.iter().zip(0..) {
+            assert!((x - k as f64 * std::f64::consts::PI).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn equation_find_root() {
+        let eq = Equation::from_str("y = x^2 + x").unwrap();
+        let interval = Interval::new(0.0, 10.0, Openness::Closed, Openness::Closed);
+        let r = eq.find_root(6.0, &interval, RootMethod::Brent, &RootOptions::default());
+        assert!(r.converged());
+        assert!((r.x - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn piecewise_invert_eval() {
+        let s = "y = {x + 1 if [-5, 5), x + 2 if [5, 10), x if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        assert_eq!(peq.invert_eval(7.0), vec![5.0]);
+        assert_eq!(peq.invert_eval(-10.0), vec![-10.0]);
+        assert!(peq.invert_eval(6.5).is_empty());
+
+        let peq = Piecewise::from_str("y = {x^3 + x if [0, 2], x^2 if otherwise}").unwrap();
+        let xs = peq.invert_eval(10.0);
+        assert_eq!(xs.len(), 3);
+        assert!((xs[0] + 10f64.sqrt()).abs() < 1e-9);
+        assert!((xs[1] - 2.0).abs() < 1e-9);
+        assert!((xs[2] - 10f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn piecewise_inverse() {
+        let s = "y = {2 * x if [0, 1), 5 - x if [1, 2], x if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        let inv = peq.inverse().unwrap();
+        assert!(inv.well_formed().is_ok());
+        for x in [0.25, 0.5, 1.0, 1.5, 2.0, -3.0] {
+            assert!((inv.eval(peq.eval(x)) - x).abs() < 1e-12);
+        }
+
+        let peq = Piecewise::from_str("y = {x^2 if [-1, 1], x if otherwise}").unwrap();
+        assert!(peq.inverse().is_err());
+        let peq = Piecewise::from_str("y = {x if [0, 2), 3 - x if [2, 3), x if otherwise}").unwrap();
+        assert!(peq.inverse().is_err());
+    }
+
+    #[test]
+    fn integrate_expr() {
+        let e = Expr::from_str("3 * x^2 + sin(x)").unwrap();
+        let interval = Interval::new(0.0, 2.0, Openness::Closed, Openness::Closed);
+        let exact = 8.0 + 1.0 - 2f64.cos();
+        let opts = QuadratureOptions::default();
+        for method in [
+            Quadrature::AdaptiveSimpson,
+            Quadrature::GaussKronrod,
+            Quadrature::Romberg,
+        ] {
+            let result = e.integrate(&interval, method, &opts);
+            assert!(result.converged, "{:?}", method);
+            assert!((result.value - exact).abs() < 1e-9, "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn integrate_nan_does_not_converge() {
+        let e = Expr::from_str("log(x, 2)").unwrap();
+        let interval = Interval::new(-1.0, 1.0, Openness::Closed, Openness::Closed);
+        let opts = QuadratureOptions::default();
+        for method in [
+            Quadrature::AdaptiveSimpson,
+            Quadrature::GaussKronrod,
+            Quadrature::Romberg,
+        ] {
+            let result = e.integrate(&interval, method, &opts);
+            assert!(!result.converged, "{:?}", method);
+            assert!(result.value.is_nan(), "{:?}", method);
+        }
+        let s = "y = {log(x, 2) if [-1, 0), x if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        let result = peq.integrate(&interval, Quadrature::GaussKronrod, &opts);
+        assert!(!result.converged && result.value.is_nan());
+    }
+
+    #[test]
+    fn integrate_piecewise_splits_at_boundaries() {
+        let s = "y = {x + 1 if [-5, 5), x + 2 if [5, 10), 0 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        let interval = Interval::new(0.0, 12.0, Openness::Closed, Openness::Closed);
+        let opts = QuadratureOptions::default();
+        // 0..5 of x + 1, 5..10 of x + 2, then zero
+        let exact = 17.5 + 47.5;
+        for method in [
+            Quadrature::AdaptiveSimpson,
+            Quadrature::GaussKronrod,
+            Quadrature::Romberg,
+        ] {
+            let result = peq.integrate(&interval, method, &opts);
+            assert!(result.converged, "{:?}", method);
+            assert!((result.value - exact).abs() < 1e-9, "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn antiderivative_polynomial() {
+        let e = Expr::from_str("3 * x^2 + 2 * x + 1").unwrap();
+        let anti = e.antiderivative("x").unwrap();
+        assert_eq!(anti.eval(2.0) - anti.eval(0.0), 14.0);
+        assert_eq!(anti.derivative("x").eval(3.0), e.eval(3.0));
+    }
+
+    #[test]
+    fn antiderivative_elementary() {
+        let cases = [
+            "sin(2 * x + 1)",
+            "4 * cos(x) - x",
+            "2.718281828459045 ^ (3 * x)",
+            "log(2 * x, 10)",
+            "(x + 1) ^ 0.5",
+            "sqrt(3 * x + 1)",
+            "1 / (x + 2)",
+        ];
+        for s in cases {
+            let e = Expr::from_str(s).unwrap();
+            let anti = e.antiderivative("x").unwrap();
+            let d = anti.derivative("x");
+            for x in [0.5, 1.0, 2.5] {
+                assert!((d.eval(x) - e.eval(x)).abs() < 1e-9, "{}", s);
+            }
+        }
+        assert!(Expr::from_str("sin(x^2)")
+            .unwrap()
+            .antiderivative("x")
+            .is_err());
+
+        // reciprocals integrate to ln(|u|), defined on both sides of the pole
+        for s in ["3 / (2 * x + 4)", "(1 - x) ^ -1"] {
+            let e = Expr::from_str(s).unwrap();
+            let d = e.antiderivative("x").unwrap().derivative("x");
+            for x in [-5.0, -1.0, 0.5, 3.0] {
+                assert!((d.eval(x) - e.eval(x)).abs() < 1e-9, "{} at {}", s, x);
+            }
+        }
+    }
+
+    #[test]
+    fn antiderivative_piecewise_is_continuous() {
+        let s = "y = {x if [0, 1), 1 if [2, 3), 0 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        let anti = peq.antiderivative("x").unwrap();
+        assert!(anti.well_formed().is_ok());
+        let eps = 1e-9;
+        for p in [0.0, 1.0, 2.0, 3.0] {
+            assert!((anti.eval(p - eps) - anti.eval(p)).abs() < 1e-6);
+        }
+        assert_eq!(anti.eval(-5.0), 0.0);
+        assert_eq!(anti.eval(1.5), 0.5);
+        assert_eq!(anti.eval(10.0), 1.5);
+    }
+
+    #[test]
+    fn maclaurin_series() {
+        let e = Expr::from_str("sin(x)").unwrap();
+        let poly = e.maclaurin("x", 5).unwrap();
+        assert_eq!(
+            poly.to_string(),
+            "((x + (-0.16666666666666666 * (x^3))) + (0.008333333333333333 * (x^5)))"
+        );
+        for x in [-0.5, 0.1, 0.5] {
+            assert!((poly.eval(x) - e.eval(x)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn taylor_series() {
+        let e = Expr::from_str("log(x, 2.718281828459045)").unwrap();
+        let poly = e.taylor("x", 1.0, 6).unwrap();
+        assert!(poly.free_vars().contains("x"));
+        for x in [0.9, 1.0, 1.2] {
+            assert!((poly.eval(x) - e.eval(x)).abs() < 1e-5);
+        }
+        assert!(e.maclaurin("x", 2).is_err());
+
+        // other variables stay symbolic
+        let e = Expr::from_str("a * cos(x)").unwrap();
+        let poly = e.maclaurin("x", 2).unwrap();
+        let env = HashMap::from([("a".to_string(), 2.0), ("x".to_string(), 0.1)]);
+        assert!((poly.eval_with(&env).unwrap() - 2.0 * (1.0 - 0.005)).abs() < 1e-12);
+
+        // high orders stay cheap, since coefficients are built from the lower ones
+        let e = Expr::from_str("sin(x) * cos(x) / (1 + x^2)").unwrap();
+        let poly = e.maclaurin("x", 24).unwrap();
+        assert!((poly.eval(0.3) - e.eval(0.3)).abs() < 1e-12);
+        let e = Expr::from_str("log(x, 2) + 2^x + acos(x / 2)").unwrap();
+        let poly = e.taylor("x", 1.0, 30).unwrap();
+        assert!((poly.eval(1.1) - e.eval(1.1)).abs() < 1e-12);
+
+        assert!(Expr::from_str("x^0.5").unwrap().maclaurin("x", 1).is_err());
+        let e = Expr::from_str("x < 0 ? -x : x^3").unwrap();
+        assert_eq!(e.taylor("x", 2.0, 1).unwrap().eval(3.0), 20.0);
+    }
+
+    #[test]
+    fn polynomial_approximation() {
+        let e = Expr::from_str("sin(x)").unwrap();
+        let interval = Interval::new(0.0, 1.5, Openness::Closed, Openness::Closed);
+        let cheb = e
+            .approximate_polynomial("x", &interval, 5, ApproxMethod::Chebyshev)
+            .unwrap();
+        let remez = e
+            .approximate_polynomial("x", &interval, 5, ApproxMethod::Remez)
+            .unwrap();
+        assert!(cheb.max_abs_error < 1e-5);
+        assert!(remez.max_abs_error <= cheb.max_abs_error);
+        assert!((remez.expr.eval(1.0) - 1f64.sin()).abs() <= remez.max_abs_error * 1.01);
+        assert!(remez.expr.to_rust().unwrap().contains("x"));
+
+        let fixed = remez.expr.to_fixed::<fixed::types::I16F16>();
+        let y = fixed.eval(fixed::types::I16F16::from_num(1.0));
+        assert!((y.to_num::<f64>() - 1f64.sin()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rational_approximation() {
+        let e = Expr::from_str("log(x, 2.718281828459045)").unwrap();
+        let interval = Interval::new(1.0, 2.0, Openness::Closed, Openness::Closed);
+        let poly = e
+            .approximate_polynomial("x", &interval, 4, ApproxMethod::Remez)
+            .unwrap();
+        let rational = e.approximate_rational("x", &interval, 2, 2).unwrap();
+        assert!(rational.max_abs_error < 1e-5);
+        assert!(rational.max_abs_error < poly.max_abs_error);
+        assert!(rational.max_rel_error.is_finite());
+    }
+
+    #[test]
+    fn approximation_needs_a_single_var() {
+        let e = Expr::from_str("x * y").unwrap();
+        let interval = Interval::new(0.0, 1.0, Openness::Closed, Openness::Closed);
+        assert!(e
+            .approximate_polynomial("x", &interval, 2, ApproxMethod::Chebyshev)
+            .is_err());
+        assert!(e.approximate_rational("x", &interval, 1, 1).is_err());
+        assert!(e
+            .piecewise_linear("z", "x", &interval, Segmentation::Uniform(4))
+            .is_err());
+        assert!(e
+            .lookup_table("z", "x", &interval, 4, Interpolation::Nearest)
+            .is_err());
+
+        // a let bound name is not a second variable
+        let e = Expr::from_str("let r = x + 1 in r * r").unwrap();
+        assert!(e
+            .approximate_polynomial("x", &interval, 2, ApproxMethod::Chebyshev)
+            .is_ok());
+    }
+
+    #[test]
+    fn piecewise_linear_generation() {
+        let e = Expr::from_str("x^2").unwrap();
+        let interval = Interval::new(0.0, 4.0, Openness::Closed, Openness::Closed);
+        let uniform = e
+            .piecewise_linear("y", "x", &interval, Segmentation::Uniform(4))
+            .unwrap();
+        assert!(uniform.well_formed().is_ok());
+        assert_eq!(uniform.eval(2.5), 6.5);
+        assert_eq!(uniform.eval(4.0), 16.0);
+        assert_eq!(uniform.eval(5.0), 25.0);
+
+        let adaptive = e
+            .piecewise_linear(
+                "y",
+                "x",
+                &interval,
+                Segmentation::Adaptive { max_error: 1e-3 },
+            )
+            .unwrap();
+        assert!(adaptive.well_formed().is_ok());
+        for x in [0.1, 1.3, 2.7, 3.9] {
+            assert!((adaptive.eval(x) - e.eval(x)).abs() <= 1e-3);
+        }
+        let round_trip = Piecewise::from_str(&adaptive.to_string()).unwrap();
+        assert_eq!(round_trip.eval(1.3), adaptive.eval(1.3));
+
+        // segments are lines in the caller's variable
+        let e = Expr::from_str("t^2").unwrap();
+        let adaptive = e
+            .piecewise_linear(
+                "y",
+                "t",
+                &interval,
+                Segmentation::Adaptive { max_error: 1e-2 },
+            )
+            .unwrap();
+        for (_, expr) in &adaptive.cases {
+            assert!(expr.free_vars().iter().all(|v| v == "t"), "{}", expr);
+        }
+        assert!((adaptive.eval(2.7) - 2.7 * 2.7).abs() <= 1e-2);
+    }
+
+    #[test]
+    fn lookup_table_generation() {
+        let e = Expr::from_str("sin(x)").unwrap();
+        let interval = Interval::new(0.0, 1.0, Openness::Closed, Openness::Open);
+        let nearest = e
+            .lookup_table("y", "x", &interval, 100, Interpolation::Nearest)
+            .unwrap();
+        let linear = e
+            .lookup_table("y", "x", &interval, 100, Interpolation::Linear)
+            .unwrap();
+        assert!(nearest.well_formed().is_ok());
+        assert!(linear.well_formed().is_ok());
+        assert_eq!(nearest.eval(0.005), 0.005f64.sin());
+        assert!((nearest.eval(0.5) - e.eval(0.5)).abs() < 1e-2);
+        assert!((linear.eval(0.5) - e.eval(0.5)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn piecewise_boolean_conditions() {
+        let s = "z = {0 if x < 0 or x > 10, 1 if x in [0, 1), 2 * x if not x == 5 and x >= 1, -1 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        assert!(peq.well_formed().is_ok());
+        assert_eq!(peq.eval(-3.0), 0.0);
+        assert_eq!(peq.eval(0.5), 1.0);
+        assert_eq!(peq.eval(2.0), 4.0);
+        assert_eq!(peq.eval(5.0), -1.0);
+
+        let round_trip = Piecewise::from_str(&peq.to_string()).unwrap();
+        assert_eq!(round_trip.to_string(), peq.to_string());
+        for x in [-3.0, 0.5, 2.0, 5.0, 11.0] {
+            assert_eq!(round_trip.eval(x), peq.eval(x));
+        }
+    }
+
+    #[test]
+    fn piecewise_multi_variable_conditions() {
+        let s = "z = {1 if x ^ 2 + y ^ 2 <= 1, 2 if (x > 0 and y in [0, 5]), 0 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        let env = |x: f64, y: f64| HashMap::from([("x".to_string(), x), ("y".to_string(), y)]);
+        assert_eq!(peq.eval_with(&env(0.5, 0.5)).unwrap(), 1.0);
+        assert_eq!(peq.eval_with(&env(2.0, 3.0)).unwrap(), 2.0);
+        assert_eq!(peq.eval_with(&env(-2.0, 3.0)).unwrap(), 0.0);
+        assert!(peq.eval_with(&HashMap::new()).is_err());
+
+        let bare = Piecewise::from_str("z = {1 if [0, 1], 0 if otherwise}").unwrap();
+        assert!(bare.eval_with(&env(0.5, 0.5)).is_err());
+        assert!(bare.antiderivative("x").is_ok());
+        assert!(peq.antiderivative("x").is_err());
+    }
+
+    #[test]
+    fn piecewise_to_rust() {
+        let s = "y = {x if [0, 1), 2 if x >= 1 and not x > 3, 0 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        assert_eq!(
+            peq.to_rust("x"),
+            "if (x >= 0_f64 && x < 1_f64) { x } else if ((x >= 1_f64) && !(x > 3_f64)) { 2_f64 } else { 0_f64 }"
+        );
+    }
+
+    #[test]
+    fn piecewise_boxes() {
+        let s = "z = {x + y if x in [0, 1) and y in [0, 1), x - y if x in [1, 2] and y in [0, 1), 0 if y in [1, 2]}";
+        let peq = Piecewise::from_str(s).unwrap();
+        assert!(peq.well_formed().is_err());
+        // the last box leaves x unbounded, so both sides of [0, 2] are open
+        assert_eq!(peq.coverage_gaps().unwrap().len(), 2);
+        assert_eq!(
+            peq.inputs().into_iter().collect::<Vec<_>>(),
+            vec!["x".to_string(), "y".to_string()]
+        );
+
+        let s = "z = {x + y if x in [0, 1) and y in [0, 1), x - y if x in [1, 2] and y in [0, 1), 0 if x in [0, 2] and y in [1, 2]}";
+        let peq = Piecewise::from_str(s).unwrap();
+        assert!(peq.well_formed().is_ok());
+        assert!(peq.coverage_gaps().unwrap().is_empty());
+        let env = |x: f64, y: f64| HashMap::from([("x".to_string(), x), ("y".to_string(), y)]);
+        assert_eq!(peq.eval_with(&env(0.5, 0.25)).unwrap(), 0.75);
+        assert_eq!(peq.eval_with(&env(1.5, 0.25)).unwrap(), 1.25);
+        assert_eq!(peq.eval_with(&env(2.0, 2.0)).unwrap(), 0.0);
+        assert!(peq.eval_with(&env(3.0, 0.0)).is_err());
+        // eval binds x and y alike, and outside every box there is no case
+        assert_eq!(peq.eval(0.5), 1.0);
+        assert!(peq.eval(3.0).is_nan());
+        assert!(peq.to_rust("x").ends_with("else { f64::NAN }"));
+
+        let s = "z = {1 if x in [0, 1] and y in [0, 1], 2 if x in [1, 2] and y in [1, 2], 0 if otherwise}";
+        let err = Piecewise::from_str(s).unwrap().well_formed().unwrap_err();
+        assert!(err.to_string().contains("overlapping boxes"));
+
+        let s = "z = {1 if x in [5, 3] and y in [0, 1], 0 if otherwise}";
+        let err = Piecewise::from_str(s).unwrap().well_formed().unwrap_err();
+        assert!(err.to_string().contains("inverted interval [5, 3]"));
+
+        let s = "z = {1 if x in [0, 1) and y in [0, 1], 2 if x in (1, 2] and y in [0, 1]}";
+        let gaps = Piecewise::from_str(s).unwrap().coverage_gaps().unwrap();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0]["x"].to_string(), "[1, 1]");
+        assert_eq!(gaps[0]["y"].to_string(), "[0, 1]");
+    }
+
+    #[test]
+    fn piecewise_coverage_report() {
+        let s = "y = {x ^ 2 if [0, 1), 2 * x - 1 if [1, 2), 5 if (3, 4], 0 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        let opts = CoverageOptions {
+            derivatives: true,
+            ..CoverageOptions::default()
+        };
+        let report = peq.coverage_report(&opts).unwrap();
+        assert!(report.otherwise_reachable);
+        assert_eq!(report.gaps.len(), 1);
+        assert_eq!(report.gaps[0].to_string(), "[2, 3]");
+
+        let at: Vec<f64> = report.boundaries.iter().map(|b| b.at).collect();
+        assert_eq!(at, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        let continuous: Vec<bool> = report.boundaries.iter().map(|b| b.continuous).collect();
+        assert_eq!(continuous, vec![true, true, false, false, false]);
+        assert_eq!(report.boundaries[0].derivative_continuous, Some(true));
+        assert_eq!(report.boundaries[1].derivative_continuous, Some(true));
+        assert_eq!(report.boundaries[2].left, 3.0);
+        assert!(!report.continuous());
+
+        let abs =
+            Piecewise::from_str("y = {0 - x if [-1, 0), x if [0, 1], x if otherwise}").unwrap();
+        let report = abs.coverage_report(&opts).unwrap();
+        assert!(report.gaps.is_empty());
+        assert_eq!(report.boundaries.len(), 3);
+        assert!(!report.boundaries[0].continuous);
+        assert!(report.boundaries[1].continuous);
+        assert_eq!(report.boundaries[1].derivative_continuous, Some(false));
+    }
+
+    #[test]
+    fn compiled_piecewise_matches_linear_scan() {
+        // alternate closed and open ends, leave gaps and single points
+        let mut cases = Vec::new();
+        for k in 0..1000 {
+            let low = k as f64;
+            let (interval, expr) = match k % 4 {
+                0 => (
+                    Interval::new(low, low + 1.0, Openness::Closed, Openness::Open),
+                    "x",
+                ),
+                1 => (
+                    Interval::new(low, low + 1.0, Openness::Closed, Openness::Closed),
+                    "2 * x",
+                ),
+                2 => (
+                    Interval::new(low, low + 0.5, Openness::Open, Openness::Open),
+                    "x ^ 2",
+                ),
+                _ => (
+                    Interval::new(low + 0.5, low + 0.5, Openness::Closed, Openness::Closed),
+                    "1",
+                ),
+            };
+            cases.push((
+                CaseCondition::Interval(interval),
+                Box::new(Expr::from_str(expr).unwrap()),
+            ));
+        }
+        cases.push((CaseCondition::Otherwise, Box::new(Expr::Const(-1.0))));
+        let peq = Piecewise::new("y".to_string(), cases);
+        let compiled = peq.compile().unwrap();
+        assert_eq!(compiled.lhs(), "y");
+
+        for k in -8..=4008 {
+            let x = k as f64 / 4.0;
+            assert_eq!(
+                compiled.eval(x).to_bits(),
+                peq.eval(x).to_bits(),
+                "x = {}",
+                x
+            );
+        }
+        assert_eq!(compiled.eval(f64::NAN), -1.0);
+
+        let general = Piecewise::from_str("y = {1 if x > 0, 0 if otherwise}").unwrap();
+        assert!(general.compile().is_err());
+
+        // binary search relies on each interval being in order
+        let inverted =
+            Piecewise::from_str("y = {1 if [0, 1), 2 if [5, 3], 0 if otherwise}").unwrap();
+        let err = inverted.compile().err().unwrap();
+        assert!(err.to_string().contains("inverted interval [5, 3]"));
+        let err = inverted
+            .to_fixed::<fixed::types::I16F16>()
+            .well_formed()
+            .unwrap_err();
+        assert!(err.to_string().contains("inverted interval [5, 3]"));
+    }
+
+    #[test]
+    fn piecewise_arithmetic() {
+        let p = Piecewise::from_str("y = {x if [0, 2), 0 if otherwise}").unwrap();
+        let q = Piecewise::from_str("z = {1 if [1, 3], 0 if otherwise}").unwrap();
+        let sum = p.add(&q).unwrap();
+        assert!(sum.well_formed().is_ok());
+        assert_eq!(
+            sum.to_string(),
+            "y = {x if [0, 1), (x + 1) if [1, 2), 1 if [2, 3], 0 if otherwise}"
+        );
+        let product = p.mul(&q).unwrap();
+        assert_eq!(product.to_string(), "y = {x if [1, 2), 0 if otherwise}");
+        for x in [-1.0, 0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 4.0] {
+            assert_eq!(sum.eval(x), p.eval(x) + q.eval(x));
+            assert_eq!(product.eval(x), p.eval(x) * q.eval(x));
+        }
+
+        let shifted = p.add_expr(&Expr::from_str("1").unwrap()).unwrap();
+        assert_eq!(
+            shifted.to_string(),
+            "y = {(x + 1) if [0, 2), 1 if otherwise}"
+        );
+        let scaled = p.mul_expr(&Expr::from_str("2 * x").unwrap()).unwrap();
+        assert_eq!(scaled.eval(1.5), 4.5);
+    }
+
+    #[test]
+    fn piecewise_merge_and_restrict() {
+        let s = "y = {x if [0, 1), x if [1, 2), 0 if [2, 3), x if [4, 5], 0 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        let merged = peq.merge_cases().unwrap();
+        assert_eq!(
+            merged.to_string(),
+            "y = {x if [0, 2), x if [4, 5], 0 if otherwise}"
+        );
+
+        let restricted = peq
+            .restrict(&Interval::new(0.5, 4.5, Openness::Closed, Openness::Open))
+            .unwrap();
+        assert!(restricted.well_formed().is_ok());
+        assert_eq!(restricted.eval(0.5), 0.5);
+        assert_eq!(restricted.eval(3.5), 0.0);
+        assert_eq!(restricted.eval(4.25), 4.25);
+        assert!(restricted.eval(0.25).is_nan());
+        assert!(restricted.eval(4.5).is_nan());
+
+        // NaN outside the interval survives a round trip through text
+        let peq = Piecewise::from_str("y = {x if otherwise}").unwrap();
+        let restricted = peq
+            .restrict(&Interval::new(0.0, 1.0, Openness::Closed, Openness::Closed))
+            .unwrap();
+        assert_eq!(
+            restricted.to_string(),
+            "y = {x if [0, 1], NaN if otherwise}"
+        );
+        let reparsed = Piecewise::from_str(&restricted.to_string()).unwrap();
+        assert!(reparsed.eval(5.0).is_nan());
+        assert!(Expr::from_str("NaN * x").unwrap().eval(1.0).is_nan());
+        let relaxed = Expr::from_str_relaxed("2 + NaN x").unwrap();
+        assert!(relaxed.eval(1.0).is_nan());
+    }
+
+    #[test]
+    fn piecewise_compose() {
+        let outer = Piecewise::from_str("y = {x ^ 2 if [0, 1), 1 if otherwise}").unwrap();
+        let inner = Piecewise::from_str("x = {t / 2 if [0, 4), 0 - t if otherwise}").unwrap();
+        let composed = outer.compose(&inner).unwrap();
+        assert!(composed.well_formed().is_ok());
+        for t in [-2.0, -0.5, 0.0, 1.0, 1.9, 2.0, 3.0, 4.0, 6.0] {
+            assert_eq!(composed.eval(t), outer.eval(inner.eval(t)), "t = {}", t);
+        }
+
+        let shift = Piecewise::from_expr("x".to_string(), Expr::from_str("t - 1").unwrap());
+        let composed = outer.compose(&shift).unwrap();
+        assert_eq!(
+            composed.to_string(),
+            "y = {((t - 1)^2) if (0 <= (t - 1) and (t - 1) < 1), 1 if otherwise}"
+        );
+        let round_trip = Piecewise::from_str(&composed.to_string()).unwrap();
+        assert_eq!(round_trip.eval(1.5), 0.25);
+    }
+
+    #[test]
+    fn fixed_point_powers_and_logs() {
+        use fixed::types::I32F32;
+
+        let error = |text: &str, x: f64| {
+            let e = Expr::from_str(text).unwrap();
+            let fixed = e.to_fixed::<I32F32>().eval(I32F32::from_num(x));
+            let exact = e.eval(x);
+            (fixed.to_num::<f64>() - exact).abs() / exact.abs().max(1.0)
+        };
+        for x in [0.001, 0.5, 1.0, 2.0, 10.0, 1000.0] {
+            for text in ["log(x, 2)", "log(x, 10)", "x^0.5", "x^-1.5", "2^(x / 100)"] {
+                assert!(error(text, x) < 1e-6, "{} at {}", text, x);
+            }
+        }
+
+        // integer powers are exact, even of negative bases
+        let e = Expr::from_str("x^3 + x^-2").unwrap().to_fixed::<I32F32>();
+        assert_eq!(e.eval(I32F32::from_num(-2)), I32F32::from_num(-7.75));
+        // the rest have no real value, and no NaN to give
+        let e = Expr::from_str("x^0.5").unwrap().to_fixed::<I32F32>();
+        assert_eq!(e.eval(I32F32::from_num(-4)), I32F32::from_num(0));
+        let e = Expr::from_str("log(x, 2)").unwrap().to_fixed::<I32F32>();
+        assert!(e.eval(I32F32::from_num(0)) < -31);
+    }
+
+    #[test]
+    fn fixed_point_piecewise() {
+        use fixed::types::{I16F16, I8F8};
+
+        let s = "y = {x + 1 if [-5, 5), 2 * x if [5, 10), x if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        assert!(peq.check_representable::<I16F16>().is_ok());
+        let fixed = peq.to_fixed::<I16F16>();
+        assert!(fixed.well_formed().is_ok());
+        let eval = |x: f64| fixed.eval(I16F16::from_num(x)).to_num::<f64>();
+        assert_eq!(eval(-5.0), -4.0);
+        assert_eq!(eval(4.5), 5.5);
+        assert_eq!(eval(5.0), 10.0);
+        assert_eq!(eval(10.0), 10.0);
+        assert_eq!(eval(-7.25), -7.25);
+
+        // an unbounded tail saturates to a closed bound at the type's range
+        let tail = Piecewise::new(
+            "y".to_string(),
+            vec![
+                (
+                    CaseCondition::Interval(Interval::new(
+                        f64::NEG_INFINITY,
+                        0.0,
+                        Openness::Open,
+                        Openness::Open,
+                    )),
+                    Box::new(Expr::Const(-1.0)),
+                ),
+                (CaseCondition::Otherwise, Box::new(Expr::Const(1.0))),
+            ],
+        );
+        assert!(tail.check_representable::<I8F8>().is_ok());
+        let tail = tail.to_fixed::<I8F8>();
+        assert_eq!(tail.eval(I8F8::MIN), -1);
+        assert_eq!(tail.eval(I8F8::ZERO), 1);
+
+        let coarse = Piecewise::from_str("y = {1 if [0, 0.1), 0 if otherwise}").unwrap();
+        assert!(coarse.check_representable::<I8F8>().is_err());
+        let wide = Piecewise::from_str("y = {1 if [0, 1000), 0 if otherwise}").unwrap();
+        assert!(wide.check_representable::<I8F8>().is_err());
+        assert!(wide.check_representable::<I16F16>().is_ok());
+
+        let overlapping = Piecewise::from_str("y = {1 if [0, 2], 0 if [2, 3), 0 if otherwise}")
+            .unwrap()
+            .to_fixed::<I16F16>();
+        assert!(overlapping.well_formed().is_err());
+    }
+
+    #[test]
+    fn unbounded_intervals() {
+        let s = "y = {0 - x if (-inf, 0), x if [0, 10], 10 if x in (10, ∞), 0 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        assert!(peq.well_formed().is_ok());
+        assert_eq!(
+            peq.to_string(),
+            "y = {(0 - x) if (-inf, 0), x if [0, 10], 10 if x in (10, inf), 0 if otherwise}"
+        );
+        assert_eq!(
+            Piecewise::from_str(&peq.to_string()).unwrap().to_string(),
+            peq.to_string()
+        );
+        assert_eq!(peq.eval(-1e300), 1e300);
+        assert_eq!(peq.eval(f64::NEG_INFINITY), f64::INFINITY);
+        assert_eq!(peq.eval(5.0), 5.0);
+        assert_eq!(peq.eval(1e300), 10.0);
+        assert!(peq.coverage_report(&Default::default()).is_err());
+
+        let everything = Interval::from_str("(-inf, inf)").unwrap();
+        assert_eq!(everything.to_string(), "(-inf, inf)");
+        assert!(everything.contains(f64::MAX));
+        assert!(Interval::from_str("[-inf, 0)").is_err());
+        assert_eq!(
+            Interval::from_str("(-inf, 0)").unwrap().to_rust("x"),
+            "(x < 0_f64)"
+        );
+        assert_eq!(everything.to_rust("x"), "true");
+
+        let abs =
+            Piecewise::from_str("y = {0 - x if (-inf, 0), x if [0, inf), 0 if otherwise}").unwrap();
+        let report = abs.coverage_report(&Default::default()).unwrap();
+        assert!(!report.otherwise_reachable);
+        assert!(report.continuous());
+        let compiled = abs.compile().unwrap();
+        assert_eq!(compiled.eval(-3.0), 3.0);
+        assert_eq!(compiled.eval(f64::NEG_INFINITY), f64::INFINITY);
+        let integral = abs.antiderivative("x").unwrap();
+        assert_eq!(integral.eval(-2.0), -2.0);
+        assert_eq!(integral.eval(2.0), 2.0);
+
+        let overlapping = Piecewise::from_str("y = {1 if [0, inf), 2 if [5, 6], 0 if otherwise}");
+        assert!(overlapping.unwrap().well_formed().is_err());
+
+        let fixed = abs.to_fixed::<fixed::types::I16F16>();
+        assert!(fixed.well_formed().is_ok());
+        let minus_three = fixed::types::I16F16::from_num(-3);
+        assert_eq!(fixed.eval(minus_three), 3);
+        assert_eq!(
+            fixed.eval(fixed::types::I16F16::MAX),
+            fixed::types::I16F16::MAX
+        );
+    }
+
+    #[test]
+    fn numeric_literals() {
+        let cases = [
+            ("1e-3", 1e-3),
+            ("6.02E23", 6.02e23),
+            ("2.5e+2", 250.0),
+            ("1.", 1.0),
+            ("1.e2", 100.0),
+            (".5", 0.5),
+            ("1_000_000", 1e6),
+            ("0.000_1", 1e-4),
+            ("0x7FFF", 32767.0),
+            ("0X1_0", 16.0),
+            ("0b1010", 10.0),
+            ("-0x10", -16.0),
+        ];
+        for (text, value) in cases {
+            let e = Expr::from_str(text).unwrap();
+            assert_eq!(e.eval(0.0), value, "{}", text);
+            let round_trip = Expr::from_str(&e.to_string()).unwrap();
+            assert_eq!(round_trip.eval(0.0), value, "{}", text);
+        }
+        assert!(Expr::from_str("0x1_0000_0000_0000_0000").is_err());
+        // integers above 2^53 must be exact, or they are rejected
+        assert_eq!(
+            Expr::from_str("0x20_0000_0000_0000").unwrap().eval(0.0),
+            2f64.powi(53)
+        );
+        assert!(Expr::from_str("0x20_0000_0000_0001").is_err());
+        assert!(Expr::from_str("0xFFFF_FFFF_FFFF_FFFF").is_err());
+        assert_eq!(
+            Expr::from_str("0xFFFF_FFFF_FFFF_F800").unwrap().eval(0.0),
+            18446744073709549568.0
+        );
+
+        // large and small magnitudes display in exponent form and read back exactly
+        let e = Expr::from_str("x * 1e300 + 1.2345e-7 - 6.02e23 + 0.00001").unwrap();
+        assert_eq!(
+            e.to_string(),
+            "((((x * 1e300) + 1.2345e-7) - 6.02e23) + 0.00001)"
+        );
+        assert!(Expr::from_str(&e.to_string()).unwrap() == e);
+        let i = Interval::new(-1e20, 1e16, Openness::Closed, Openness::Open);
+        assert_eq!(i.to_string(), "[-1e20, 1e16)");
+        assert_eq!(Expr::Const(f64::MAX).to_string(), "1.7976931348623157e308");
+        assert_eq!(Expr::Const(-5e-324).to_string(), "-5e-324");
+        assert_eq!(Expr::Const(1234567.5).to_string(), "1234567.5");
+
+        let e = Expr::from_str("x * 1e300 + 1e-300 - 2.5e-7").unwrap();
+        assert_eq!(
+            e.to_rust().unwrap(),
+            "(((x * 1e300_f64) + 1e-300_f64) - 2.5e-7_f64)"
+        );
+        assert_eq!(
+            Expr::from_str("x ^ 1.5").unwrap().to_rust().unwrap(),
+            "x.powf(1.5_f64)"
+        );
+        let power = Expr::Pow(
+            Box::new(Expr::Const(-2.0)),
+            Box::new(Expr::Var("x".to_string())),
+        );
+        assert_eq!(power.to_rust().unwrap(), "(-2_f64).powf(x)");
+        assert_eq!(
+            Expr::Const(f64::NEG_INFINITY).to_rust().unwrap(),
+            "(-f64::INFINITY)"
+        );
+
+        let s = "y = {0 if [0x10, 1e2), 1 if otherwise}";
+        let peq = Piecewise::from_str(s).unwrap();
+        assert_eq!(peq.to_string(), "y = {0 if [16, 100), 1 if otherwise}");
+        assert_eq!(
+            peq.to_rust("x"),
+            "if (x >= 16_f64 && x < 100_f64) { 0_f64 } else { 1_f64 }"
+        );
+    }
 }
@@ -0,0 +1,228 @@
+use crate::ast::{CaseCondition, Interval, Openness, Piecewise};
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
+/// A box in input space: one interval per named variable. Variables that are
+/// not mentioned are unbounded.
+pub type Region = BTreeMap<String, Interval<f64>>;
+
+impl CaseCondition<f64> {
+    /// The box this condition describes, if it is a conjunction of
+    /// `var in interval` tests naming each variable at most once.
+    pub fn region(&self) -> Option<Region> {
+        match self {
+            CaseCondition::In(v, i) => Some(Region::from([(v.clone(), i.clone())])),
+            CaseCondition::And(a, b) => {
+                let mut region = a.region()?;
+                for (v, i) in b.region()? {
+                    if region.insert(v, i).is_some() {
+                        return None;
+                    }
+                }
+                Some(region)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The condition `v1 in I1 and v2 in I2 and ...` selecting `region`.
+fn condition(region: &Region) -> CaseCondition<f64> {
+    region
+        .iter()
+        .map(|(v, i)| CaseCondition::In(v.clone(), i.clone()))
+        .reduce(|acc, c| CaseCondition::And(Box::new(acc), Box::new(c)))
+        .unwrap_or(CaseCondition::Otherwise)
+}
+
+fn overlaps(a: &Region, b: &Region) -> bool {
+    a.iter()
+        .all(|(v, i)| b.get(v).is_none_or(|j| i.intersection(j).is_some()))
+}
+
+fn covers(region: &Region, point: &BTreeMap<&str, f64>) -> bool {
+    region.iter().all(|(v, i)| i.contains(point[v.as_str()]))
+}
+
+/// Split the line into the boundary values of `var` found in `regions` and
+/// the open stretches between them, each paired with a point inside it. The
+/// stretches beyond the outermost boundaries are included only when some
+/// region leaves `var` unbounded there.
+fn cells(var: &str, regions: &[Region]) -> Vec<(Interval<f64>, f64)> {
+    let mut bounds: Vec<f64> = regions
+        .iter()
+        .filter_map(|r| r.get(var))
+        .flat_map(|i| {
+            let low = (i.low_openness != Openness::Unbounded).then_some(i.low_val);
+            let high = (i.high_openness != Openness::Unbounded).then_some(i.high_val);
+            low.into_iter().chain(high)
+        })
+        .collect();
+    bounds.sort_by(f64::total_cmp);
+    bounds.dedup();
+    let unbounded_low = regions.iter().any(|r| {
+        r.get(var)
+            .is_none_or(|i| i.low_openness == Openness::Unbounded)
+    });
+    let unbounded_high = regions.iter().any(|r| {
+        r.get(var)
+            .is_none_or(|i| i.high_openness == Openness::Unbounded)
+    });
+
+    let open = |a: f64, b: f64| Interval::new(a, b, Openness::Open, Openness::Open);
+    let mut cells = Vec::new();
+    match (bounds.first(), bounds.last()) {
+        (Some(&first), Some(&last)) => {
+            if unbounded_low {
+                cells.push((Interval::below(first, Openness::Open), first - 1.0));
+            }
+            for (k, &b) in bounds.iter().enumerate() {
+                cells.push((Interval::new(b, b, Openness::Closed, Openness::Closed), b));
+                if let Some(&next) = bounds.get(k + 1) {
+                    cells.push((open(b, next), b + (next - b) / 2.0));
+                }
+            }
+            if unbounded_high {
+                cells.push((Interval::above(last, Openness::Open), last + 1.0));
+            }
+        }
+        _ => cells.push((Interval::real_line(), 0.0)),
+    }
+    cells
+}
+
+/// The union of `a` and `b`, when they agree in every variable but one and
+/// meet end to end in that one.
+fn merge(a: &Region, b: &Region) -> Option<Region> {
+    let mut differing = a.iter().filter(|(v, i)| b.get(*v) != Some(*i));
+    let (var, i) = differing.next()?;
+    if differing.next().is_some() || a.len() != b.len() {
+        return None;
+    }
+    let j = &b[var];
+    let (lo, hi) = if i.high_val <= j.low_val {
+        (i, j)
+    } else {
+        (j, i)
+    };
+    if lo.high_val != hi.low_val || lo.high_openness == hi.low_openness {
+        return None;
+    }
+    let mut merged = a.clone();
+    merged.insert(
+        var.clone(),
+        Interval::new(lo.low_val, hi.high_val, lo.low_openness, hi.high_openness),
+    );
+    Some(merged)
+}
+
+impl Piecewise<f64> {
+    /// The box of every case but a final `otherwise`, if each one is a box.
+    pub fn regions(&self) -> Option<Vec<Region>> {
+        self.cases
+            .iter()
+            .filter(|(cond, _)| *cond != CaseCondition::Otherwise)
+            .map(|(cond, _)| cond.region())
+            .collect::<Option<Vec<_>>>()
+            .filter(|regions| !regions.is_empty())
+    }
+
+    /// The parts of the boxes' bounding hull that no case covers.
+    ///
+    /// The hull is cut at every box boundary into a grid whose cells are, in
+    /// each variable, either a single boundary value or the open stretch
+    /// between two. Uncovered cells that meet end to end are joined, so the
+    /// boxes returned tile the gaps exactly but are not necessarily the
+    /// fewest possible. Fails unless every case but `otherwise` is a box.
+    pub fn coverage_gaps(&self) -> Result<Vec<Region>> {
+        let regions = self.regions().ok_or_else(|| {
+            anyhow!("Cannot find coverage gaps: not every case condition is a box")
+        })?;
+        let vars: Vec<String> = regions
+            .iter()
+            .flat_map(|r| r.keys().cloned())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut grid: Vec<(Region, BTreeMap<&str, f64>)> = vec![(Region::new(), BTreeMap::new())];
+        for var in &vars {
+            let cells = cells(var, &regions);
+            grid = grid
+                .into_iter()
+                .flat_map(|(region, point)| {
+                    cells.iter().map(move |(interval, x)| {
+                        let mut region = region.clone();
+                        let mut point = point.clone();
+                        region.insert(var.clone(), interval.clone());
+                        point.insert(var.as_str(), *x);
+                        (region, point)
+                    })
+                })
+                .collect();
+        }
+
+        let mut gaps: Vec<Region> = grid
+            .into_iter()
+            .filter(|(_, point)| !regions.iter().any(|r| covers(r, point)))
+            .map(|(region, _)| region)
+            .collect();
+
+        // coalesce neighbouring cells until no two can be joined
+        let mut k = 0;
+        while k < gaps.len() {
+            match (k + 1..gaps.len()).find_map(|l| Some((l, merge(&gaps[k], &gaps[l])?))) {
+                Some((l, merged)) => {
+                    gaps.remove(l);
+                    gaps[k] = merged;
+                }
+                None => k += 1,
+            }
+        }
+        Ok(gaps)
+    }
+
+    /// Checks a piecewise function whose cases are all boxes: no two boxes
+    /// may overlap, and without an `otherwise` case the boxes must leave no
+    /// gaps in their bounding hull.
+    pub(crate) fn well_formed_regions(&self, regions: &[Region]) -> Result<()> {
+        let last = self.cases.len() - 1;
+        if self.cases[..last]
+            .iter()
+            .any(|(cond, _)| *cond == CaseCondition::Otherwise)
+        {
+            return Err(anyhow!("Malformed piecewise function: \"otherwise\" should only be the last piecewise case"));
+        }
+        if let Some(i) = regions
+            .iter()
+            .flat_map(|r| r.values())
+            .find(|i| i.is_inverted())
+        {
+            return Err(anyhow!(
+                "Malformed piecewise function: inverted interval {}",
+                i
+            ));
+        }
+        for (k, a) in regions.iter().enumerate() {
+            for b in &regions[k + 1..] {
+                if overlaps(a, b) {
+                    return Err(anyhow!(
+                        "Malformed piecewise function: overlapping boxes {} and {}",
+                        condition(a),
+                        condition(b)
+                    ));
+                }
+            }
+        }
+
+        if self.cases[last].0 != CaseCondition::Otherwise {
+            if let Some(gap) = self.coverage_gaps()?.first() {
+                return Err(anyhow!(
+                    "Malformed piecewise function: no case covers {}",
+                    condition(gap)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+//! A forgiving front end for text written the way people write math by hand.
+//!
+//! Relaxed parsing rewrites its input into the strict syntax and hands that
+//! to the ordinary parser, so it builds exactly the trees the strict form
+//! would. On top of the strict syntax it accepts:
+//!
+//! - implicit multiplication, when an operand is directly followed by a
+//!   variable, a function or an opening parenthesis: `3x^2`, `2(x + 1)`,
+//!   `(x + 1)(x - 1)`, `2 sin(x)`. The inserted `*` has its usual precedence,
+//!   so `3x^2` is `3 * (x^2)` and `1/2x` is `(1/2) * x`. Two numbers in a row
+//!   are still an error.
+//! - `sin`, `cos`, `asin`, `acos` and `sqrt` applied to a single number or variable
+//!   without parentheses: `sin x` is `sin(x)`. Powers of the argument go with
+//!   it, so `sin x^2` is `sin(x^2)`; write `sin(x)^2` for the other reading.
+//! - `**` as an alias for `^`.
+//! - `×` and `·` for `*`, `÷` for `/` and `−` for `-`.
+//!
+//! A run of letters and digits is still one variable, so `xy` is not `x * y`.
+//! Positions in parse errors refer to the rewritten text.
+
+use crate::ast::{Equation, Expr, Piecewise};
+use anyhow::Result;
+use std::str::FromStr;
+
+/// Words the strict grammar reserves, which never take part in implicit
+/// multiplication.
+const KEYWORDS: [&str; 9] = [
+    "if",
+    "otherwise",
+    "in",
+    "and",
+    "or",
+    "not",
+    "inf",
+    "let",
+    "where",
+];
+
+/// Functions that may be applied to a single atom without parentheses.
+const UNARY_FUNCTIONS: [&str; 5] = ["sin", "cos", "asin", "acos", "sqrt"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Number,
+    Name,
+    Function,
+    Keyword,
+    Symbol,
+}
+
+struct Token {
+    kind: Kind,
+    text: String,
+}
+
+/// The length of the numeric literal at the start of `s`, following the
+/// strict grammar's literal syntax. An exponent or radix prefix is only taken
+/// when digits follow it, so `2e` is `2` followed by the variable `e`.
+fn number_len(s: &[char]) -> usize {
+    let digits_from = |start: usize, ok: fn(char) -> bool| {
+        start
+            + s[start..]
+                .iter()
+                .take_while(|&&c| ok(c) || c == '_')
+                .count()
+    };
+    if s.len() > 2 && s[0] == '0' {
+        let radix_digit: Option<fn(char) -> bool> = match s[1] {
+            'x' | 'X' => Some(|c| c.is_ascii_hexdigit()),
+            'b' | 'B' => Some(|c| c == '0' || c == '1'),
+            _ => None,
+        };
+        if let Some(ok) = radix_digit.filter(|ok| ok(s[2])) {
+            return digits_from(2, ok);
+        }
+    }
+    let decimal = |c: char| c.is_ascii_digit();
+    let mut end = digits_from(0, decimal);
+    if s.get(end) == Some(&'.') {
+        end = digits_from(end + 1, decimal);
+    }
+    if matches!(s.get(end), Some('e' | 'E')) {
+        let sign = usize::from(matches!(s.get(end + 1), Some('+' | '-')));
+        if s.get(end + 1 + sign).is_some_and(|c| c.is_ascii_digit()) {
+            end = digits_from(end + 1 + sign, decimal);
+        }
+    }
+    end
+}
+
+fn tokenize(s: &str) -> Vec<Token> {
+    let chars: Vec<char> = s
+        .chars()
+        .map(|c| match c {
+            '×' | '·' => '*',
+            '÷' => '/',
+            '−' => '-',
+            c => c,
+        })
+        .collect();
+    let mut tokens = Vec::new();
+    let mut k = 0;
+    while k < chars.len() {
+        let rest = &chars[k..];
+        let c = rest[0];
+        let (kind, len) = if c.is_whitespace() {
+            k += 1;
+            continue;
+        } else if c.is_ascii_digit() || (c == '.' && rest.get(1).is_some_and(char::is_ascii_digit))
+        {
+            (Kind::Number, number_len(rest))
+        } else if c.is_ascii_alphabetic() {
+            let len = rest
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .count();
+            let word: String = rest[..len].iter().collect();
+            // as in the strict grammar, "if(" is the function and "if" the keyword
+            let called = word == "if" && rest.get(len) == Some(&'(');
+            let kind = if word == "NaN" {
+                Kind::Number
+            } else if KEYWORDS.contains(&word.as_str()) && !called {
+                Kind::Keyword
+            } else if UNARY_FUNCTIONS.contains(&word.as_str()) || word == "log" || called {
+                Kind::Function
+            } else {
+                Kind::Name
+            };
+            (kind, len)
+        } else if matches!(rest, ['*', '*', ..]) {
+            tokens.push(Token {
+                kind: Kind::Symbol,
+                text: "^".into(),
+            });
+            k += 2;
+            continue;
+        } else if matches!(rest, ['<' | '>' | '=' | '!', '=', ..]) {
+            (Kind::Symbol, 2)
+        } else {
+            (Kind::Symbol, 1)
+        };
+        tokens.push(Token {
+            kind,
+            text: rest[..len].iter().collect(),
+        });
+        k += len;
+    }
+    tokens
+}
+
+/// Whether `t` can be the last token of an operand.
+fn ends_operand(t: &Token) -> bool {
+    matches!(t.kind, Kind::Number | Kind::Name) || t.text == ")"
+}
+
+/// Whether `t` can be the first token of an operand that implicit
+/// multiplication may apply to.
+fn starts_operand(t: &Token) -> bool {
+    matches!(t.kind, Kind::Name | Kind::Function) || t.text == "("
+}
+
+/// The number of tokens in the `^` and optionally signed atom at the start
+/// of `tokens`, if there is one.
+fn power_len(tokens: &[Token]) -> Option<usize> {
+    if tokens.first()?.text != "^" {
+        return None;
+    }
+    let sign = usize::from(tokens.get(1)?.text == "-");
+    let atom = tokens.get(1 + sign)?;
+    matches!(atom.kind, Kind::Number | Kind::Name).then_some(2 + sign)
+}
+
+/// Rewrite relaxed syntax into the strict syntax the grammar accepts.
+pub fn to_strict(s: &str) -> String {
+    let tokens = tokenize(s);
+    let mut out: Vec<String> = Vec::new();
+    let mut prev: Option<&Token> = None;
+    let mut k = 0;
+    while k < tokens.len() {
+        let t = &tokens[k];
+        if prev.is_some_and(ends_operand) && starts_operand(t) {
+            out.push("*".into());
+        }
+        let next = tokens.get(k + 1);
+        match next {
+            // the grammar reads a function name and its "(" as one token
+            Some(n) if t.kind == Kind::Function && n.text == "(" => {
+                out.push(format!("{}(", t.text));
+                k += 2;
+                prev = None;
+                continue;
+            }
+            Some(n)
+                if UNARY_FUNCTIONS.contains(&t.text.as_str())
+                    && matches!(n.kind, Kind::Number | Kind::Name) =>
+            {
+                // the argument takes any powers with it, as in `sin(x^2)`
+                let mut arg = n.text.clone();
+                k += 2;
+                while let Some(len) = power_len(&tokens[k..]) {
+                    for p in &tokens[k..k + len] {
+                        arg.push_str(&p.text);
+                    }
+                    k += len;
+                }
+                out.push(format!("{}({})", t.text, arg));
+                // the application is an operand in its own right
+                prev = Some(&tokens[k - 1]);
+                continue;
+            }
+            _ => out.push(t.text.clone()),
+        }
+        prev = Some(t);
+        k += 1;
+    }
+    out.join(" ")
+}
+
+fn parse<T: FromStr<Err = anyhow::Error>>(s: &str) -> Result<T> {
+    T::from_str(&to_strict(s))
+}
+
+impl Expr<f64> {
+    /// Parse an expression in the relaxed syntax described in `relaxed`.
+    pub fn from_str_relaxed(s: &str) -> Result<Self> {
+        parse(s)
+    }
+}
+
+impl Equation<f64> {
+    /// Parse an equation in the relaxed syntax described in `relaxed`.
+    pub fn from_str_relaxed(s: &str) -> Result<Self> {
+        parse(s)
+    }
+}
+
+impl Piecewise<f64> {
+    /// Parse a piecewise function in the relaxed syntax described in
+    /// `relaxed`.
+    pub fn from_str_relaxed(s: &str) -> Result<Self> {
+        parse(s)
+    }
+}
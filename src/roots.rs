@@ -0,0 +1,292 @@
+use crate::ast::{Equation, Expr, Interval, Openness};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Derivative {
+    /// Differentiate the expression symbolically once, then evaluate it.
+    Symbolic,
+    /// Carry derivatives alongside values with forward-mode dual numbers.
+    Automatic,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RootMethod {
+    Bisection,
+    Brent,
+    /// Newton-Raphson, started from the midpoint of the search interval.
+    Newton(Derivative),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RootOptions {
+    /// Stop once the bracket (or Newton step) is narrower than this.
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for RootOptions {
+    fn default() -> Self {
+        RootOptions {
+            tolerance: 1e-12,
+            max_iterations: 100,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RootStatus {
+    Converged,
+    /// Ran out of iterations; `x` is the best estimate so far.
+    MaxIterations,
+    /// The function has the same sign at both ends of the interval, so a
+    /// bracketing method has nothing to work with.
+    NoSignChange,
+    /// Newton-Raphson hit a zero or non-finite derivative, or stepped
+    /// outside the search interval.
+    Diverged,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Root {
+    pub x: f64,
+    /// The residual `f(x)`.
+    pub fx: f64,
+    pub iterations: usize,
+    pub status: RootStatus,
+}
+
+impl Root {
+    pub fn converged(&self) -> bool {
+        self.status == RootStatus::Converged
+    }
+}
+
+fn root(x: f64, fx: f64, iterations: usize, status: RootStatus) -> Root {
+    Root {
+        x,
+        fx,
+        iterations,
+        status,
+    }
+}
+
+/// Check the endpoints of `[a, b]` for an exact zero or a missing sign
+/// change before a bracketing method starts.
+fn check_bracket(f: &dyn Fn(f64) -> f64, interval: &Interval<f64>) -> Result<(f64, f64), Root> {
+    let (a, b) = (interval.low_val, interval.high_val);
+    let (fa, fb) = (f(a), f(b));
+    if fa == 0.0 && interval.low_openness == Openness::Closed {
+        return Err(root(a, fa, 0, RootStatus::Converged));
+    }
+    if fb == 0.0 && interval.high_openness == Openness::Closed {
+        return Err(root(b, fb, 0, RootStatus::Converged));
+    }
+    if fa * fb > 0.0 {
+        return Err(root(a, fa, 0, RootStatus::NoSignChange));
+    }
+    Ok((fa, fb))
+}
+
+fn bisection(f: &dyn Fn(f64) -> f64, interval: &Interval<f64>, opts: &RootOptions) -> Root {
+    let (mut fa, _) = match check_bracket(f, interval) {
+        Ok(fs) => fs,
+        Err(r) => return r,
+    };
+    let (mut a, mut b) = (interval.low_val, interval.high_val);
+    for i in 1..=opts.max_iterations {
+        let m = a + (b - a) / 2.0;
+        let fm = f(m);
+        if fm == 0.0 || (b - a) / 2.0 < opts.tolerance {
+            return root(m, fm, i, RootStatus::Converged);
+        }
+        if fm * fa > 0.0 {
+            a = m;
+            fa = fm;
+        } else {
+            b = m;
+        }
+    }
+    let m = a + (b - a) / 2.0;
+    root(m, f(m), opts.max_iterations, RootStatus::MaxIterations)
+}
+
+/// Brent's method: inverse quadratic interpolation and secant steps, falling
+/// back to bisection whenever they fail to shrink the bracket fast enough.
+fn brent(f: &dyn Fn(f64) -> f64, interval: &Interval<f64>, opts: &RootOptions) -> Root {
+    let (mut fa, mut fb) = match check_bracket(f, interval) {
+        Ok(fs) => fs,
+        Err(r) => return r,
+    };
+    let (mut a, mut b) = (interval.low_val, interval.high_val);
+    let (mut c, mut fc) = (b, fb);
+    let (mut d, mut e) = (b - a, b - a);
+
+    for i in 1..=opts.max_iterations {
+        if fb * fc > 0.0 {
+            c = a;
+            fc = fa;
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            a = b;
+            b = c;
+            c = a;
+            fa = fb;
+            fb = fc;
+            fc = fa;
+        }
+
+        let tol = 2.0 * f64::EPSILON * b.abs() + 0.5 * opts.tolerance;
+        let m = 0.5 * (c - b);
+        if m.abs() <= tol || fb == 0.0 {
+            return root(b, fb, i, RootStatus::Converged);
+        }
+
+        if e.abs() >= tol && fa.abs() > fb.abs() {
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * m * s, 1.0 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (
+                    s * (2.0 * m * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            } else {
+                p = -p;
+            }
+            if 2.0 * p < (3.0 * m * q - (tol * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = m;
+                e = m;
+            }
+        } else {
+            d = m;
+            e = m;
+        }
+
+        a = b;
+        fa = fb;
+        b += if d.abs() > tol { d } else { tol.copysign(m) };
+        fb = f(b);
+    }
+    root(b, fb, opts.max_iterations, RootStatus::MaxIterations)
+}
+
+fn newton(f: &dyn Fn(f64) -> (f64, f64), interval: &Interval<f64>, opts: &RootOptions) -> Root {
+    let mut x = interval.low_val + (interval.high_val - interval.low_val) / 2.0;
+    for i in 1..=opts.max_iterations {
+        let (fx, dfx) = f(x);
+        if fx == 0.0 {
+            return root(x, fx, i, RootStatus::Converged);
+        }
+        let step = fx / dfx;
+        if !step.is_finite() {
+            return root(x, fx, i, RootStatus::Diverged);
+        }
+        x -= step;
+        if !interval.contains(x) {
+            return root(x, f(x).0, i, RootStatus::Diverged);
+        }
+        if step.abs() < opts.tolerance {
+            return root(x, f(x).0, i, RootStatus::Converged);
+        }
+    }
+    root(x, f(x).0, opts.max_iterations, RootStatus::MaxIterations)
+}
+
+impl Expr<f64> {
+    /// Find an `x` in `interval` with `self.eval(x) == 0`.
+    pub fn find_root(
+        &self,
+        interval: &Interval<f64>,
+        method: RootMethod,
+        opts: &RootOptions,
+    ) -> Root {
+        match method {
+            RootMethod::Bisection => bisection(&|x| self.eval(x), interval, opts),
+            RootMethod::Brent => brent(&|x| self.eval(x), interval, opts),
+            RootMethod::Newton(Derivative::Automatic) => {
+                newton(&|x| self.eval_with_derivative(x), interval, opts)
+            }
+            RootMethod::Newton(Derivative::Symbolic) => {
+                // `eval` binds every variable to the same argument, so the
+                // matching derivative is the sum of the partials
+                let d = self
+                    .free_vars()
+                    .iter()
+                    .map(|v| self.derivative(v))
+                    .reduce(|acc, d| Expr::Add(Box::new(acc), Box::new(d)))
+                    .unwrap_or(Expr::Const(0.0));
+                newton(&|x| (self.eval(x), d.eval(x)), interval, opts)
+            }
+        }
+    }
+
+    /// Find every root in `interval` by splitting it into `samples` equal
+    /// pieces and running Brent's method on each piece that changes sign.
+    /// Only converged roots are returned, in increasing order.
+    pub fn find_roots(
+        &self,
+        interval: &Interval<f64>,
+        samples: usize,
+        opts: &RootOptions,
+    ) -> Vec<Root> {
+        let (low, high) = (interval.low_val, interval.high_val);
+        let width = (high - low) / samples.max(1) as f64;
+        let mut roots: Vec<Root> = Vec::new();
+        for i in 0..samples.max(1) {
+            let a = low + width * i as f64;
+            let b = if i + 1 == samples.max(1) {
+                high
+            } else {
+                a + width
+            };
+            let low_openness = if i == 0 {
+                interval.low_openness
+            } else {
+                Openness::Closed
+            };
+            let high_openness = if i + 1 == samples.max(1) {
+                interval.high_openness
+            } else {
+                Openness::Open
+            };
+            let piece = Interval::new(a, b, low_openness, high_openness);
+            let r = brent(&|x| self.eval(x), &piece, opts);
+            let duplicate = roots
+                .last()
+                .is_some_and(|prev| (r.x - prev.x).abs() <= opts.tolerance);
+            if r.converged() && !duplicate {
+                roots.push(r);
+            }
+        }
+        roots
+    }
+}
+
+impl Equation<f64> {
+    /// Find an argument in `interval` at which the right-hand side evaluates
+    /// to `lhs_value`.
+    pub fn find_root(
+        &self,
+        lhs_value: f64,
+        interval: &Interval<f64>,
+        method: RootMethod,
+        opts: &RootOptions,
+    ) -> Root {
+        let shifted = Expr::Sub(
+            Box::new(self.rhs().clone()),
+            Box::new(Expr::Const(lhs_value)),
+        );
+        let mut r = shifted.find_root(interval, method, opts);
+        r.fx = self.eval(r.x) - lhs_value;
+        r
+    }
+}
@@ -0,0 +1,87 @@
+use crate::ast::{CaseCondition, Expr};
+use crate::visit::{fold_expr, Fold};
+
+/// Bottom-up constant folding plus the algebraic identities that symbolic
+/// rewrites (solving, differentiation, ...) tend to leave behind, such as
+/// `0 + e`, `1 * e` and `e ^ 1`.
+struct Simplify;
+
+impl Fold<f64> for Simplify {
+    fn fold_expr(&mut self, expr: Expr<f64>) -> Expr<f64> {
+        use Expr::*;
+        let expr = fold_expr(self, expr);
+        let leaf = matches!(expr, Const(_) | Var(_));
+        // conditions can test variables without holding them as children
+        if !leaf
+            && expr.children().iter().all(|e| matches!(e, Const(_)))
+            && expr.vars().next().is_none()
+            && !expr.has_bare_interval()
+        {
+            return Const(expr.eval(0.0));
+        }
+        match expr {
+            Add(op1, op2) => match (*op1, *op2) {
+                (Const(0.0), e) | (e, Const(0.0)) => e,
+                (e, Neg(op)) => simplify_sub(e, *op),
+                (op1, op2) => Add(Box::new(op1), Box::new(op2)),
+            },
+            Sub(op1, op2) => simplify_sub(*op1, *op2),
+            Mul(op1, op2) => match (*op1, *op2) {
+                (Const(0.0), _) | (_, Const(0.0)) => Const(0.0),
+                (Const(1.0), e) | (e, Const(1.0)) => e,
+                (Const(-1.0), e) | (e, Const(-1.0)) => Neg(Box::new(e)),
+                (op1, op2) => Mul(Box::new(op1), Box::new(op2)),
+            },
+            Div(op1, op2) => match (*op1, *op2) {
+                (e, Const(1.0)) => e,
+                (Const(0.0), _) => Const(0.0),
+                (op1, op2) => Div(Box::new(op1), Box::new(op2)),
+            },
+            Pow(op1, op2) => match (*op1, *op2) {
+                (_, Const(0.0)) => Const(1.0),
+                (e, Const(1.0)) => e,
+                (op1, op2) => Pow(Box::new(op1), Box::new(op2)),
+            },
+            Neg(op) => match *op {
+                Neg(e) => *e,
+                e => Neg(Box::new(e)),
+            },
+            If(_, a, b) if a == b => *a,
+            Let(name, _, body) if !body.contains_var(&name) => *body,
+            Let(name, value, body) if matches!(*value, Const(_) | Var(_)) => {
+                self.fold_expr(body.substitute(&name, &value))
+            }
+            If(cond, a, b) if is_constant(&cond) => {
+                if cond.holds(0.0) {
+                    *a
+                } else {
+                    *b
+                }
+            }
+            e => e,
+        }
+    }
+}
+
+fn simplify_sub(op1: Expr<f64>, op2: Expr<f64>) -> Expr<f64> {
+    use Expr::*;
+    match (op1, op2) {
+        (e, Const(0.0)) => e,
+        (Const(0.0), e) => Neg(Box::new(e)),
+        (e, Neg(op)) => Add(Box::new(e), op),
+        (op1, op2) => Sub(Box::new(op1), Box::new(op2)),
+    }
+}
+
+impl Expr<f64> {
+    pub fn simplify(&self) -> Expr<f64> {
+        Simplify.fold_expr(self.clone())
+    }
+}
+
+/// Whether `cond` compares only constants and tests no variable.
+fn is_constant(cond: &CaseCondition<f64>) -> bool {
+    cond.exprs().iter().all(|e| matches!(e, Expr::Const(_)))
+        && cond.free_vars().is_empty()
+        && !cond.tests_argument()
+}
@@ -0,0 +1,212 @@
+use crate::ast::{Equation, Expr};
+use anyhow::{anyhow, Result};
+
+type BinOp = fn(Box<Expr<f64>>, Box<Expr<f64>>) -> Expr<f64>;
+
+fn boxed(e: Expr<f64>) -> Box<Expr<f64>> {
+    Box::new(e)
+}
+
+/// Peel invertible operations off `expr` (the side containing `var`),
+/// applying their inverse to `other` until `var` stands alone. Even powers
+/// branch into a positive and a negative root.
+fn isolate(expr: &Expr<f64>, other: Expr<f64>, var: &str) -> Result<Vec<Expr<f64>>> {
+    use Expr::*;
+    let has = |e: &Expr<f64>| e.contains_var(var);
+    let c = |e: &Expr<f64>| boxed(e.clone());
+    match expr {
+        Var(s) if s == var => Ok(vec![other]),
+        Let(..) => isolate(&expr.inline_lets(), other, var),
+        Neg(op) => isolate(op, Neg(boxed(other)), var),
+        Add(op1, op2) if !has(op2) => isolate(op1, Sub(boxed(other), c(op2)), var),
+        Add(op1, op2) if !has(op1) => isolate(op2, Sub(boxed(other), c(op1)), var),
+        Sub(op1, op2) if !has(op2) => isolate(op1, Add(boxed(other), c(op2)), var),
+        Sub(op1, op2) if !has(op1) => isolate(op2, Sub(c(op1), boxed(other)), var),
+        Mul(op1, op2) if !has(op2) => isolate(op1, Div(boxed(other), c(op2)), var),
+        Mul(op1, op2) if !has(op1) => isolate(op2, Div(boxed(other), c(op1)), var),
+        Div(op1, op2) if !has(op2) => isolate(op1, Mul(boxed(other), c(op2)), var),
+        Div(op1, op2) if !has(op1) => isolate(op2, Div(c(op1), boxed(other)), var),
+        Pow(op1, op2) if matches!(**op2, Const(n) if n != 0.0 && n % 2.0 == 0.0) => {
+            let root = Pow(boxed(other), boxed(Div(boxed(Const(1.0)), c(op2))));
+            let mut sols = isolate(op1, root.clone(), var)?;
+            sols.extend(isolate(op1, Neg(boxed(root)), var)?);
+            Ok(sols)
+        }
+        Pow(op1, op2) if !has(op2) => {
+            let root = Div(boxed(Const(1.0)), c(op2));
+            isolate(op1, Pow(boxed(other), boxed(root)), var)
+        }
+        Pow(op1, op2) if !has(op1) => isolate(op2, Log(boxed(other), c(op1)), var),
+        Log(op1, op2) if !has(op2) => isolate(op1, Pow(c(op2), boxed(other)), var),
+        Log(op1, op2) if !has(op1) => {
+            let root = Div(boxed(Const(1.0)), boxed(other));
+            isolate(op2, Pow(c(op1), boxed(root)), var)
+        }
+        Sin(op) => isolate(op, Asin(boxed(other)), var),
+        Cos(op) => isolate(op, Acos(boxed(other)), var),
+        Asin(op) => isolate(op, Sin(boxed(other)), var),
+        Acos(op) => isolate(op, Cos(boxed(other)), var),
+        Sqrt(op) => isolate(op, Pow(boxed(other), boxed(Const(2.0))), var),
+        e => Err(anyhow!(
+            "Cannot solve for \"{}\": it appears non-invertibly in {}",
+            var,
+            e
+        )),
+    }
+}
+
+/// Coefficients of `expr` as a polynomial in `var`, lowest degree first.
+/// Each coefficient is itself an expression that does not mention `var`.
+pub(crate) fn polynomial(expr: &Expr<f64>, var: &str) -> Result<Vec<Expr<f64>>> {
+    use Expr::*;
+    if !expr.contains_var(var) {
+        return Ok(vec![expr.clone()]);
+    }
+    match expr {
+        Var(_) => Ok(vec![Const(0.0), Const(1.0)]),
+        Let(..) => polynomial(&expr.inline_lets(), var),
+        Neg(op) => Ok(polynomial(op, var)?
+            .into_iter()
+            .map(|c| Neg(boxed(c)))
+            .collect()),
+        Add(op1, op2) => Ok(zip_coeffs(
+            polynomial(op1, var)?,
+            polynomial(op2, var)?,
+            Add,
+        )),
+        Sub(op1, op2) => Ok(zip_coeffs(
+            polynomial(op1, var)?,
+            polynomial(op2, var)?,
+            Sub,
+        )),
+        Mul(op1, op2) => Ok(mul_coeffs(&polynomial(op1, var)?, &polynomial(op2, var)?)),
+        Div(op1, op2) if !op2.contains_var(var) => Ok(polynomial(op1, var)?
+            .into_iter()
+            .map(|c| Div(boxed(c), op2.clone()))
+            .collect()),
+        Pow(op1, op2) => match **op2 {
+            Const(n) if n >= 0.0 && n.fract() == 0.0 => {
+                let base = polynomial(op1, var)?;
+                let mut acc = vec![Const(1.0)];
+                for _ in 0..n as usize {
+                    acc = mul_coeffs(&acc, &base);
+                }
+                Ok(acc)
+            }
+            _ => Err(anyhow!(
+                "Cannot solve for \"{}\": {} is not a polynomial in it",
+                var,
+                expr
+            )),
+        },
+        e => Err(anyhow!(
+            "Cannot solve for \"{}\": {} is not a polynomial in it",
+            var,
+            e
+        )),
+    }
+}
+
+fn zip_coeffs(p: Vec<Expr<f64>>, q: Vec<Expr<f64>>, op: BinOp) -> Vec<Expr<f64>> {
+    let n = p.len().max(q.len());
+    let mut p = p.into_iter();
+    let mut q = q.into_iter();
+    (0..n)
+        .map(|_| {
+            let a = p.next().unwrap_or(Expr::Const(0.0));
+            let b = q.next().unwrap_or(Expr::Const(0.0));
+            op(boxed(a), boxed(b))
+        })
+        .collect()
+}
+
+fn mul_coeffs(p: &[Expr<f64>], q: &[Expr<f64>]) -> Vec<Expr<f64>> {
+    let mut out = vec![Expr::Const(0.0); p.len() + q.len() - 1];
+    for (i, a) in p.iter().enumerate() {
+        for (j, b) in q.iter().enumerate() {
+            let term = Expr::Mul(boxed(a.clone()), boxed(b.clone()));
+            out[i + j] = Expr::Add(boxed(out[i + j].clone()), boxed(term));
+        }
+    }
+    out
+}
+
+/// Solve `poly(var) = 0` for degree one or two.
+fn solve_polynomial(expr: &Expr<f64>, var: &str) -> Result<Vec<Expr<f64>>> {
+    use Expr::*;
+    let mut coeffs: Vec<_> = polynomial(expr, var)?.iter().map(Expr::simplify).collect();
+    while matches!(coeffs.last(), Some(Const(0.0))) {
+        coeffs.pop();
+    }
+    match coeffs.as_slice() {
+        [c, b] => Ok(vec![Div(boxed(Neg(boxed(c.clone()))), boxed(b.clone()))]),
+        [c, b, a] => {
+            let disc = Sub(
+                boxed(Pow(boxed(b.clone()), boxed(Const(2.0)))),
+                boxed(Mul(
+                    boxed(Mul(boxed(Const(4.0)), boxed(a.clone()))),
+                    boxed(c.clone()),
+                )),
+            );
+            let sqrt = Pow(boxed(disc), boxed(Const(0.5)));
+            let denom = Mul(boxed(Const(2.0)), boxed(a.clone()));
+            let neg_b = Neg(boxed(b.clone()));
+            Ok(vec![
+                Div(
+                    boxed(Add(boxed(neg_b.clone()), boxed(sqrt.clone()))),
+                    boxed(denom.clone()),
+                ),
+                Div(boxed(Sub(boxed(neg_b), boxed(sqrt))), boxed(denom)),
+            ])
+        }
+        _ if coeffs.len() <= 1 => Err(anyhow!(
+            "Cannot solve for \"{}\": it cancels out of the equation",
+            var
+        )),
+        _ => Err(anyhow!(
+            "Cannot solve for \"{}\": polynomial of degree {} is not supported",
+            var,
+            coeffs.len() - 1
+        )),
+    }
+}
+
+impl Equation<f64> {
+    /// Rearrange the equation so that `var` is the left-hand side.
+    ///
+    /// Equations that isolate `var` through a chain of invertible operations
+    /// have a single solution per branch: even powers give both signs of the
+    /// root, and `asin`/`acos` give the principal branch when inverting
+    /// trigonometric functions. Linear and quadratic
+    /// equations in `var` are solved by collecting coefficients, producing
+    /// one equation per root.
+    pub fn solve_for(&self, var: &str) -> Result<Vec<Equation<f64>>> {
+        let lhs = Expr::Var(self.lhs().to_string());
+        let rhs = self.rhs();
+        if var == self.lhs() && !rhs.contains_var(var) {
+            return Ok(vec![self.clone()]);
+        }
+        if !rhs.contains_var(var) {
+            return Err(anyhow!(
+                "Cannot solve for \"{}\": it does not appear in the equation",
+                var
+            ));
+        }
+
+        let solutions = if var == self.lhs() {
+            // `var` is on both sides, so only collecting terms can help
+            solve_polynomial(&Expr::Sub(boxed(lhs), boxed(rhs.clone())), var)?
+        } else {
+            match isolate(rhs, lhs.clone(), var) {
+                Ok(solutions) => solutions,
+                Err(e) => solve_polynomial(&Expr::Sub(boxed(rhs.clone()), boxed(lhs)), var)
+                    .map_err(|_| e)?,
+            }
+        };
+
+        Ok(solutions
+            .into_iter()
+            .map(|e| Equation::new(var.to_string(), boxed(e.simplify())))
+            .collect())
+    }
+}
@@ -0,0 +1,136 @@
+use crate::ast::{CaseCondition, CmpOp, Equation, Expr, Interval, Openness};
+use crate::visit::{fold_condition, fold_expr, Fold};
+use std::collections::{BTreeSet, HashMap};
+
+/// Replaces every `Var` found in `bindings` with a copy of its replacement.
+/// Replacements are inserted as-is and are not themselves rewritten, so the
+/// substitution is simultaneous: `{x: y, y: x}` swaps the two variables.
+struct Substitute<'a, T> {
+    bindings: &'a HashMap<String, Expr<T>>,
+}
+
+impl<T: Clone> Fold<T> for Substitute<'_, T> {
+    fn fold_expr(&mut self, expr: Expr<T>) -> Expr<T> {
+        match expr {
+            Expr::Var(s) => match self.bindings.get(&s) {
+                Some(replacement) => replacement.clone(),
+                None => Expr::Var(s),
+            },
+            // a let hides any binding of its name from its body, and is
+            // renamed if a replacement would otherwise be captured by it
+            Expr::Let(name, value, body) => {
+                let value = Box::new(self.fold_expr(*value));
+                let mut inner = self.bindings.clone();
+                inner.remove(&name);
+                let captures = inner
+                    .iter()
+                    .any(|(v, r)| body.contains_var(v) && r.free_vars().contains(&name));
+                let name = if captures {
+                    let fresh = fresh_name(&name, &body, &inner);
+                    inner.insert(name, Expr::Var(fresh.clone()));
+                    fresh
+                } else {
+                    name
+                };
+                let body = Box::new(Substitute { bindings: &inner }.fold_expr(*body));
+                Expr::Let(name, value, body)
+            }
+            e => fold_expr(self, e),
+        }
+    }
+
+    fn fold_condition(&mut self, cond: CaseCondition<T>) -> CaseCondition<T> {
+        match cond {
+            CaseCondition::In(v, i) => match self.bindings.get(&v) {
+                Some(Expr::Var(renamed)) => CaseCondition::In(renamed.clone(), i),
+                Some(replacement) => within(replacement, &i),
+                None => CaseCondition::In(v, i),
+            },
+            c => fold_condition(self, c),
+        }
+    }
+}
+
+/// `name` followed by the first number that makes it a name `body` does not
+/// mention and no replacement reads.
+fn fresh_name<T>(name: &str, body: &Expr<T>, bindings: &HashMap<String, Expr<T>>) -> String {
+    let taken: BTreeSet<String> = body
+        .vars()
+        .map(str::to_string)
+        .chain(body.bound_vars())
+        .chain(bindings.values().flat_map(Expr::free_vars))
+        .collect();
+    (1..)
+        .map(|k| format!("{}{}", name, k))
+        .find(|fresh| !taken.contains(fresh))
+        .unwrap()
+}
+
+/// `low < expr < high`, with each comparison strict only at an open end and
+/// left out at an unbounded one.
+pub(crate) fn within<T: Clone>(expr: &Expr<T>, i: &Interval<T>) -> CaseCondition<T> {
+    let cmp = |o: Openness| match o {
+        Openness::Open => Some(CmpOp::Lt),
+        Openness::Closed => Some(CmpOp::Le),
+        Openness::Unbounded => None,
+    };
+    let low = cmp(i.low_openness).map(|op| {
+        CaseCondition::Compare(
+            op,
+            Box::new(Expr::Const(i.low_val.clone())),
+            Box::new(expr.clone()),
+        )
+    });
+    let high = cmp(i.high_openness).map(|op| {
+        CaseCondition::Compare(
+            op,
+            Box::new(expr.clone()),
+            Box::new(Expr::Const(i.high_val.clone())),
+        )
+    });
+    match (low, high) {
+        (Some(low), Some(high)) => CaseCondition::And(Box::new(low), Box::new(high)),
+        (Some(bound), None) | (None, Some(bound)) => bound,
+        // like the whole line, holds for everything but NaN
+        (None, None) => {
+            CaseCondition::Compare(CmpOp::Eq, Box::new(expr.clone()), Box::new(expr.clone()))
+        }
+    }
+}
+
+impl<T: Clone> Expr<T> {
+    pub fn substitute(&self, var: &str, replacement: &Expr<T>) -> Expr<T> {
+        let bindings = HashMap::from([(var.to_string(), replacement.clone())]);
+        self.substitute_all(&bindings)
+    }
+
+    pub fn substitute_all(&self, bindings: &HashMap<String, Expr<T>>) -> Expr<T> {
+        Substitute { bindings }.fold_expr(self.clone())
+    }
+
+    /// The same expression with every `let` bound name replaced by its
+    /// value, so that nothing is bound any more.
+    pub fn inline_lets(&self) -> Expr<T> {
+        struct Inline;
+        impl<T: Clone> Fold<T> for Inline {
+            fn fold_expr(&mut self, expr: Expr<T>) -> Expr<T> {
+                match fold_expr(self, expr) {
+                    Expr::Let(name, value, body) => body.substitute(&name, &value),
+                    e => e,
+                }
+            }
+        }
+        Inline.fold_expr(self.clone())
+    }
+}
+
+impl<T: Clone> Equation<T> {
+    /// Inline `inner` into this equation: every occurrence of `inner.lhs()` on
+    /// our right-hand side is replaced by `inner.rhs()`.
+    ///
+    /// Composing `y = 2 * x` with `x = t + 1` gives `y = 2 * (t + 1)`.
+    pub fn compose(&self, inner: &Equation<T>) -> Equation<T> {
+        let rhs = self.rhs().substitute(inner.lhs(), inner.rhs());
+        Equation::new(self.lhs().to_string(), Box::new(rhs))
+    }
+}
@@ -0,0 +1,134 @@
+use crate::ast::Equation;
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display};
+
+/// A set of equations where the `lhs` of one may be an input of another.
+///
+/// Equations are stored in dependency order: every equation comes after all
+/// of the equations that define its inputs, so evaluating them front to back
+/// always has the values it needs.
+#[derive(Clone)]
+pub struct EquationSystem<T> {
+    equations: Vec<Equation<T>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Unvisited,
+    InProgress,
+    Done,
+}
+
+impl<T> EquationSystem<T> {
+    /// Order `equations` by dependency, rejecting duplicate definitions and
+    /// cyclic dependencies (including an equation that reads its own `lhs`).
+    pub fn new(equations: Vec<Equation<T>>) -> Result<Self> {
+        let mut index = HashMap::new();
+        for (i, eq) in equations.iter().enumerate() {
+            if index.insert(eq.lhs().to_string(), i).is_some() {
+                return Err(anyhow!(
+                    "Malformed equation system: \"{}\" is defined more than once",
+                    eq.lhs()
+                ));
+            }
+        }
+
+        let mut marks = vec![Mark::Unvisited; equations.len()];
+        let mut order = Vec::with_capacity(equations.len());
+        let mut path = Vec::new();
+        for i in 0..equations.len() {
+            Self::visit(i, &equations, &index, &mut marks, &mut path, &mut order)?;
+        }
+
+        let mut slots: Vec<_> = equations.into_iter().map(Some).collect();
+        let equations = order.into_iter().filter_map(|i| slots[i].take()).collect();
+        Ok(EquationSystem { equations })
+    }
+
+    // Depth-first post-order walk; `path` holds the chain of definitions
+    // currently being resolved so that a cycle can be reported in full.
+    fn visit(
+        i: usize,
+        equations: &[Equation<T>],
+        index: &HashMap<String, usize>,
+        marks: &mut [Mark],
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<()> {
+        match marks[i] {
+            Mark::Done => return Ok(()),
+            Mark::InProgress => {
+                let start = path.iter().position(|&j| j == i).unwrap_or(0);
+                let cycle = path[start..]
+                    .iter()
+                    .chain(std::iter::once(&i))
+                    .map(|&j| equations[j].lhs())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                return Err(anyhow!(
+                    "Malformed equation system: cyclic dependency {}",
+                    cycle
+                ));
+            }
+            Mark::Unvisited => {}
+        }
+
+        marks[i] = Mark::InProgress;
+        path.push(i);
+        for var in equations[i].inputs() {
+            if let Some(&j) = index.get(&var) {
+                Self::visit(j, equations, index, marks, path, order)?;
+            }
+        }
+        path.pop();
+        marks[i] = Mark::Done;
+        order.push(i);
+        Ok(())
+    }
+
+    /// The equations in dependency order.
+    pub fn equations(&self) -> &[Equation<T>] {
+        &self.equations
+    }
+
+    /// The variables defined by the system, in dependency order.
+    pub fn outputs(&self) -> Vec<&str> {
+        self.equations.iter().map(|eq| eq.lhs()).collect()
+    }
+
+    /// The root inputs: variables read by some equation but defined by none.
+    pub fn inputs(&self) -> BTreeSet<String> {
+        let outputs: BTreeSet<_> = self.outputs().into_iter().collect();
+        self.equations
+            .iter()
+            .flat_map(|eq| eq.inputs())
+            .filter(|v| !outputs.contains(v.as_str()))
+            .collect()
+    }
+}
+
+impl EquationSystem<f64> {
+    /// Evaluate every equation given values for the root inputs. The result
+    /// holds the inputs together with all of the computed outputs.
+    pub fn eval(&self, inputs: &HashMap<String, f64>) -> Result<HashMap<String, f64>> {
+        let mut env = inputs.clone();
+        for eq in &self.equations {
+            let v = eq.eval_with(&env)?;
+            env.insert(eq.lhs().to_string(), v);
+        }
+        Ok(env)
+    }
+}
+
+impl<T: Display> fmt::Display for EquationSystem<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let eqns = self
+            .equations
+            .iter()
+            .map(|eq| eq.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        write!(f, "{}", eqns)
+    }
+}
@@ -1,24 +1,83 @@
-/*
 use cordic::CordicNumber;
+use fixed::types::U0F64;
 
 use crate::fixed_point::CordicMarker;
 
-// a^b = c^(b*log_c(a)))
-pub fn cordic_powf<T: CordicNumber + CordicMarker>(a: T, b:T) -> T {
-    cordic::exp(b * cordic_elog(a))
+/// `a` to the power `b`, as e^(b ln a) except for integer powers, which are
+/// exact products. A fractional power of a non-positive base, which has no
+/// real value, is zero.
+pub fn cordic_powf<T: CordicNumber + CordicMarker>(a: T, b: T) -> T {
+    if b.floor() == b {
+        powi(a, b)
+    } else if a <= T::zero() {
+        T::zero()
+    } else {
+        cordic::exp(b * cordic_ln(a))
+    }
 }
 
-// log_e(a)
-// pub fn cordic_elog<T: CordicNumber + CordicMarker>(a: T) -> T {
-    
-// }
+/// The logarithm of `a` to the base `b`, as ln(a) / ln(b).
+pub fn cordic_log<T: CordicNumber + CordicMarker>(a: T, b: T) -> T {
+    cordic_ln(a) / cordic_ln(b)
+}
+
+/// The natural logarithm of `a`. Fixed point has no infinity or NaN, so a
+/// non-positive `a` gives the logarithm of the smallest positive value.
+pub fn cordic_ln<T: CordicNumber + CordicMarker>(a: T) -> T {
+    let ulp = T::one() >> T::num_fract_bits();
+    // a = m * 2^k with m in [1, 2)
+    let mut m = if a < ulp { ulp } else { a };
+    let mut k: i32 = 0;
+    while m >= T::one() + T::one() {
+        m = m >> 1;
+        k += 1;
+    }
+    while m < T::one() {
+        m = m << 1;
+        k -= 1;
+    }
 
+    // Halley's method on e^y = m, from the first term of 2 atanh((m-1)/(m+1))
+    let mut y = ((m - T::one()) << 1) / (m + T::one());
+    for _ in 0..3 {
+        let e = cordic::exp(y);
+        y += ((m - e) << 1) / (m + e);
+    }
 
-// log_b(a) = log_c(a) / log_c(b)
-pub fn cordic_log<T: CordicNumber + CordicMarker>(a: T, b:T) -> T {
-    taylor_elog(a) / taylor_elog(b)
+    let ln_2 = T::from_u0f64(U0F64::from_num(fixed::consts::LN_2));
+    for _ in 0..k.unsigned_abs() {
+        if k < 0 {
+            y -= ln_2;
+        } else {
+            y += ln_2;
+        }
+    }
+    y
 }
 
+/// `a` to the integer power `b`, by repeated squaring.
+fn powi<T: CordicNumber + CordicMarker>(mut a: T, b: T) -> T {
+    let mut n = if b < T::zero() { -b } else { b };
+    let mut product = T::one();
+    loop {
+        let half = (n >> 1).floor();
+        if n != half << 1 {
+            product = product * a;
+        }
+        n = half;
+        if n == T::zero() {
+            break;
+        }
+        a = a * a;
+    }
+    if b < T::zero() {
+        T::one() / product
+    } else {
+        product
+    }
+}
+
+/*
 
 /// Implement logarithm using tanh identity
 /// impl<T: CordicNumber + CordicMarker> Expr<T> {
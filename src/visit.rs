@@ -0,0 +1,349 @@
+//! Generic traversals over `Expr` trees.
+//!
+//! `Visitor` and `VisitorMut` walk an expression by reference, `Fold` rebuilds
+//! it by value. Each trait method defaults to the matching `walk_*` / `fold_*`
+//! free function, so an implementation only overrides the nodes it cares
+//! about and keeps recursing through everything else.
+
+use crate::ast::{CaseCondition, Expr, Interval};
+
+pub trait Visitor<T> {
+    fn visit_expr(&mut self, expr: &Expr<T>) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_const(&mut self, _c: &T) {}
+
+    fn visit_var(&mut self, _name: &str) {}
+}
+
+/// Visit every direct child of `expr`, dispatching constants and variables
+/// to their dedicated callbacks.
+pub fn walk_expr<T, V: Visitor<T> + ?Sized>(v: &mut V, expr: &Expr<T>) {
+    use Expr::*;
+    match expr {
+        Const(c) => v.visit_const(c),
+        Var(s) => v.visit_var(s),
+        Add(op1, op2)
+        | Sub(op1, op2)
+        | Mul(op1, op2)
+        | Div(op1, op2)
+        | Pow(op1, op2)
+        | Log(op1, op2) => {
+            v.visit_expr(op1);
+            v.visit_expr(op2);
+        }
+        Neg(op) | Sin(op) | Cos(op) | Asin(op) | Acos(op) | Sqrt(op) => v.visit_expr(op),
+        Bool(c) => walk_condition(v, c),
+        If(c, a, b) => {
+            walk_condition(v, c);
+            v.visit_expr(a);
+            v.visit_expr(b);
+        }
+        Let(name, value, body) => {
+            v.visit_var(name);
+            v.visit_expr(value);
+            v.visit_expr(body);
+        }
+    }
+}
+
+/// Visit the expressions `cond` compares or tests, and the variables of its
+/// `in` tests, left to right.
+fn walk_condition<T, V: Visitor<T> + ?Sized>(v: &mut V, cond: &CaseCondition<T>) {
+    use CaseCondition::*;
+    match cond {
+        Otherwise | Interval(_) => {}
+        In(name, _) => v.visit_var(name),
+        Compare(_, a, b) => {
+            v.visit_expr(a);
+            v.visit_expr(b);
+        }
+        And(a, b) | Or(a, b) => {
+            walk_condition(v, a);
+            walk_condition(v, b);
+        }
+        Not(c) => walk_condition(v, c),
+        NonZero(e) => v.visit_expr(e),
+    }
+}
+
+pub trait VisitorMut<T> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr<T>) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_const_mut(&mut self, _c: &mut T) {}
+
+    fn visit_var_mut(&mut self, _name: &mut String) {}
+}
+
+pub fn walk_expr_mut<T, V: VisitorMut<T> + ?Sized>(v: &mut V, expr: &mut Expr<T>) {
+    use Expr::*;
+    match expr {
+        Const(c) => v.visit_const_mut(c),
+        Var(s) => v.visit_var_mut(s),
+        Add(op1, op2)
+        | Sub(op1, op2)
+        | Mul(op1, op2)
+        | Div(op1, op2)
+        | Pow(op1, op2)
+        | Log(op1, op2) => {
+            v.visit_expr_mut(op1);
+            v.visit_expr_mut(op2);
+        }
+        Neg(op) | Sin(op) | Cos(op) | Asin(op) | Acos(op) | Sqrt(op) => v.visit_expr_mut(op),
+        Bool(c) => walk_condition_mut(v, c),
+        If(c, a, b) => {
+            walk_condition_mut(v, c);
+            v.visit_expr_mut(a);
+            v.visit_expr_mut(b);
+        }
+        Let(name, value, body) => {
+            v.visit_var_mut(name);
+            v.visit_expr_mut(value);
+            v.visit_expr_mut(body);
+        }
+    }
+}
+
+fn walk_condition_mut<T, V: VisitorMut<T> + ?Sized>(v: &mut V, cond: &mut CaseCondition<T>) {
+    use CaseCondition::*;
+    match cond {
+        Otherwise | Interval(_) => {}
+        In(name, _) => v.visit_var_mut(name),
+        Compare(_, a, b) => {
+            v.visit_expr_mut(a);
+            v.visit_expr_mut(b);
+        }
+        And(a, b) | Or(a, b) => {
+            walk_condition_mut(v, a);
+            walk_condition_mut(v, b);
+        }
+        Not(c) => walk_condition_mut(v, c),
+        NonZero(e) => v.visit_expr_mut(e),
+    }
+}
+
+pub trait Fold<T> {
+    fn fold_expr(&mut self, expr: Expr<T>) -> Expr<T> {
+        fold_expr(self, expr)
+    }
+
+    fn fold_const(&mut self, c: T) -> T {
+        c
+    }
+
+    fn fold_var(&mut self, name: String) -> String {
+        name
+    }
+
+    fn fold_condition(&mut self, cond: CaseCondition<T>) -> CaseCondition<T> {
+        fold_condition(self, cond)
+    }
+}
+
+/// Rebuild `expr` bottom-up, folding each child before its parent is
+/// reassembled.
+pub fn fold_expr<T, F: Fold<T> + ?Sized>(f: &mut F, expr: Expr<T>) -> Expr<T> {
+    use Expr::*;
+    match expr {
+        Const(c) => Const(f.fold_const(c)),
+        Var(s) => Var(f.fold_var(s)),
+        Add(op1, op2) => Add(fold_boxed(f, op1), fold_boxed(f, op2)),
+        Sub(op1, op2) => Sub(fold_boxed(f, op1), fold_boxed(f, op2)),
+        Mul(op1, op2) => Mul(fold_boxed(f, op1), fold_boxed(f, op2)),
+        Div(op1, op2) => Div(fold_boxed(f, op1), fold_boxed(f, op2)),
+        Pow(op1, op2) => Pow(fold_boxed(f, op1), fold_boxed(f, op2)),
+        Log(op1, op2) => Log(fold_boxed(f, op1), fold_boxed(f, op2)),
+        Neg(op) => Neg(fold_boxed(f, op)),
+        Sin(op) => Sin(fold_boxed(f, op)),
+        Cos(op) => Cos(fold_boxed(f, op)),
+        Asin(op) => Asin(fold_boxed(f, op)),
+        Acos(op) => Acos(fold_boxed(f, op)),
+        Sqrt(op) => Sqrt(fold_boxed(f, op)),
+        Bool(c) => Bool(Box::new(f.fold_condition(*c))),
+        If(c, a, b) => If(
+            Box::new(f.fold_condition(*c)),
+            fold_boxed(f, a),
+            fold_boxed(f, b),
+        ),
+        Let(name, value, body) => Let(f.fold_var(name), fold_boxed(f, value), fold_boxed(f, body)),
+    }
+}
+
+/// Rebuild `cond` with every expression it compares or tests folded, and
+/// the variables of its `in` tests passed through `fold_var`.
+pub fn fold_condition<T, F: Fold<T> + ?Sized>(
+    f: &mut F,
+    cond: CaseCondition<T>,
+) -> CaseCondition<T> {
+    use CaseCondition::*;
+    match cond {
+        Compare(op, a, b) => Compare(op, fold_boxed(f, a), fold_boxed(f, b)),
+        And(a, b) => And(
+            Box::new(f.fold_condition(*a)),
+            Box::new(f.fold_condition(*b)),
+        ),
+        Or(a, b) => Or(
+            Box::new(f.fold_condition(*a)),
+            Box::new(f.fold_condition(*b)),
+        ),
+        Not(c) => Not(Box::new(f.fold_condition(*c))),
+        NonZero(e) => NonZero(fold_boxed(f, e)),
+        In(name, i) => In(f.fold_var(name), i),
+        c @ (Otherwise | Interval(_)) => c,
+    }
+}
+
+fn fold_boxed<T, F: Fold<T> + ?Sized>(f: &mut F, mut expr: Box<Expr<T>>) -> Box<Expr<T>> {
+    *expr = f.fold_expr(*expr);
+    expr
+}
+
+impl<T> Expr<T> {
+    /// The immediate operands of this node, left to right.
+    pub fn children(&self) -> Vec<&Expr<T>> {
+        use Expr::*;
+        match self {
+            Const(_) | Var(_) => vec![],
+            Add(op1, op2)
+            | Sub(op1, op2)
+            | Mul(op1, op2)
+            | Div(op1, op2)
+            | Pow(op1, op2)
+            | Log(op1, op2) => vec![op1, op2],
+            Neg(op) | Sin(op) | Cos(op) | Asin(op) | Acos(op) | Sqrt(op) => vec![op],
+            Bool(c) => c.exprs(),
+            If(c, a, b) => {
+                let mut children = c.exprs();
+                children.extend([&**a, &**b]);
+                children
+            }
+            Let(_, value, body) => vec![value, body],
+        }
+    }
+
+    /// Rebuild the expression with every constant converted by `f`, e.g. to
+    /// move between numeric representations.
+    pub fn map_consts<U>(&self, f: &impl Fn(&T) -> U) -> Expr<U> {
+        use Expr::*;
+        let m = |e: &Expr<T>| Box::new(e.map_consts(f));
+        match self {
+            Const(c) => Const(f(c)),
+            Var(s) => Var(s.clone()),
+            Add(op1, op2) => Add(m(op1), m(op2)),
+            Sub(op1, op2) => Sub(m(op1), m(op2)),
+            Mul(op1, op2) => Mul(m(op1), m(op2)),
+            Div(op1, op2) => Div(m(op1), m(op2)),
+            Pow(op1, op2) => Pow(m(op1), m(op2)),
+            Log(op1, op2) => Log(m(op1), m(op2)),
+            Neg(op) => Neg(m(op)),
+            Sin(op) => Sin(m(op)),
+            Cos(op) => Cos(m(op)),
+            Asin(op) => Asin(m(op)),
+            Acos(op) => Acos(m(op)),
+            Sqrt(op) => Sqrt(m(op)),
+            Bool(c) => Bool(Box::new(c.map_consts(f))),
+            If(c, a, b) => If(Box::new(c.map_consts(f)), m(a), m(b)),
+            Let(name, value, body) => Let(name.clone(), m(value), m(body)),
+        }
+    }
+
+    /// Pre-order iterator over this expression and all of its subexpressions.
+    pub fn subexprs(&self) -> Subexprs<'_, T> {
+        Subexprs { stack: vec![self] }
+    }
+
+    /// Every variable occurrence in pre-order, including repeats, the
+    /// variables of `in` tests and uses of `let` bound names.
+    pub fn vars(&self) -> impl Iterator<Item = &str> {
+        self.subexprs().flat_map(|e| match e {
+            Expr::Var(s) => vec![s.as_str()],
+            Expr::Bool(c) | Expr::If(c, _, _) => c.tested_vars(),
+            _ => vec![],
+        })
+    }
+}
+
+impl<T> CaseCondition<T> {
+    /// The expressions the condition compares or tests, left to right,
+    /// looking through `and`, `or` and `not`.
+    pub fn exprs(&self) -> Vec<&Expr<T>> {
+        use CaseCondition::*;
+        match self {
+            Otherwise | Interval(_) | In(..) => vec![],
+            Compare(_, a, b) => vec![a, b],
+            And(a, b) | Or(a, b) => [a.exprs(), b.exprs()].concat(),
+            Not(c) => c.exprs(),
+            NonZero(e) => vec![e],
+        }
+    }
+
+    pub fn exprs_mut(&mut self) -> Vec<&mut Expr<T>> {
+        use CaseCondition::*;
+        match self {
+            Otherwise | Interval(_) | In(..) => vec![],
+            Compare(_, a, b) => vec![a, b],
+            And(a, b) | Or(a, b) => {
+                let mut exprs = a.exprs_mut();
+                exprs.extend(b.exprs_mut());
+                exprs
+            }
+            Not(c) => c.exprs_mut(),
+            NonZero(e) => vec![e],
+        }
+    }
+
+    /// The variables of the `in` tests, which are not expressions of their
+    /// own.
+    pub(crate) fn tested_vars(&self) -> Vec<&str> {
+        use CaseCondition::*;
+        match self {
+            In(v, _) => vec![v.as_str()],
+            And(a, b) | Or(a, b) => [a.tested_vars(), b.tested_vars()].concat(),
+            Not(c) => c.tested_vars(),
+            _ => vec![],
+        }
+    }
+
+    pub fn map_consts<U>(&self, f: &impl Fn(&T) -> U) -> CaseCondition<U> {
+        use CaseCondition::*;
+        let m = |c: &CaseCondition<T>| Box::new(c.map_consts(f));
+        match self {
+            Otherwise => Otherwise,
+            Interval(i) => Interval(i.map_consts(f)),
+            In(v, i) => In(v.clone(), i.map_consts(f)),
+            Compare(op, a, b) => Compare(*op, Box::new(a.map_consts(f)), Box::new(b.map_consts(f))),
+            And(a, b) => And(m(a), m(b)),
+            Or(a, b) => Or(m(a), m(b)),
+            Not(c) => Not(m(c)),
+            NonZero(e) => NonZero(Box::new(e.map_consts(f))),
+        }
+    }
+}
+
+impl<T> Interval<T> {
+    pub fn map_consts<U>(&self, f: &impl Fn(&T) -> U) -> Interval<U> {
+        Interval::new(
+            f(&self.low_val),
+            f(&self.high_val),
+            self.low_openness,
+            self.high_openness,
+        )
+    }
+}
+
+pub struct Subexprs<'a, T> {
+    stack: Vec<&'a Expr<T>>,
+}
+
+impl<'a, T> Iterator for Subexprs<'a, T> {
+    type Item = &'a Expr<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let e = self.stack.pop()?;
+        self.stack.extend(e.children().into_iter().rev());
+        Some(e)
+    }
+}